@@ -0,0 +1,1012 @@
+use super::decode::{Mnemonic, OperandSize};
+use super::flags::Flags;
+
+/// Returns the mask covering all bits of an operand of the given size.
+pub fn mask(size: OperandSize) -> u16 {
+    match size {
+        OperandSize::Byte => 0x00FF,
+        OperandSize::Word => 0xFFFF,
+    }
+}
+
+/// Returns the mask of the sign bit of an operand of the given size.
+pub fn sign_bit(size: OperandSize) -> u16 {
+    match size {
+        OperandSize::Byte => 0x0080,
+        OperandSize::Word => 0x8000,
+    }
+}
+
+/// Returns true if the low byte of `value` has an even number of set bits.
+pub fn parity(value: u16) -> bool {
+    (value as u8).count_ones().is_multiple_of(2)
+}
+
+/// Sets ZF, SF and PF from a result of the given size.
+pub fn set_result_flags(flags: &mut Flags, result: u16, size: OperandSize) {
+    let result = result & mask(size);
+    flags.set_zero(result == 0);
+    flags.set_sign(result & sign_bit(size) != 0);
+    flags.set_parity(parity(result));
+}
+
+/// Adds `source` and the carry-in to `destination`, setting CF, PF, AF, ZF, SF and OF.
+pub fn add(
+    flags: &mut Flags,
+    destination: u16,
+    source: u16,
+    carry: bool,
+    size: OperandSize,
+) -> u16 {
+    let mask = mask(size);
+    let (destination, source) = (destination & mask, source & mask);
+    let wide = destination as u32 + source as u32 + carry as u32;
+    let result = wide as u16 & mask;
+
+    flags.set_carry(wide > mask as u32);
+    flags.set_auxiliary_carry((destination ^ source ^ result) & 0x10 != 0);
+    flags.set_overflow((destination ^ result) & (source ^ result) & sign_bit(size) != 0);
+    set_result_flags(flags, result, size);
+    result
+}
+
+/// Subtracts `source` and the borrow-in from `destination`, setting CF, PF, AF, ZF, SF and OF.
+pub fn sub(
+    flags: &mut Flags,
+    destination: u16,
+    source: u16,
+    borrow: bool,
+    size: OperandSize,
+) -> u16 {
+    let mask = mask(size);
+    let (destination, source) = (destination & mask, source & mask);
+    let result = destination.wrapping_sub(source).wrapping_sub(borrow as u16) & mask;
+
+    flags.set_carry((source as u32 + borrow as u32) > destination as u32);
+    flags.set_auxiliary_carry((destination ^ source ^ result) & 0x10 != 0);
+    flags.set_overflow((destination ^ source) & (destination ^ result) & sign_bit(size) != 0);
+    set_result_flags(flags, result, size);
+    result
+}
+
+/// Increments `value` by one. CF is left untouched.
+pub fn inc(flags: &mut Flags, value: u16, size: OperandSize) -> u16 {
+    let carry = flags.get_carry();
+    let result = add(flags, value, 1, false, size);
+    flags.set_carry(carry);
+    result
+}
+
+/// Decrements `value` by one. CF is left untouched.
+pub fn dec(flags: &mut Flags, value: u16, size: OperandSize) -> u16 {
+    let carry = flags.get_carry();
+    let result = sub(flags, value, 1, false, size);
+    flags.set_carry(carry);
+    result
+}
+
+/// Negates `value` (two's complement). CF is set unless the operand is zero.
+pub fn neg(flags: &mut Flags, value: u16, size: OperandSize) -> u16 {
+    sub(flags, 0, value, false, size)
+}
+
+/// Sets the flags for the result of a logical operation (AND, OR, XOR, TEST).
+///
+/// CF and OF are cleared. AF is documented as undefined; the 8086 clears it.
+fn set_logic_flags(flags: &mut Flags, result: u16, size: OperandSize) {
+    flags.set_carry(false);
+    flags.set_overflow(false);
+    flags.set_auxiliary_carry(false);
+    set_result_flags(flags, result, size);
+}
+
+/// Bitwise AND of `destination` and `source`.
+pub fn and(flags: &mut Flags, destination: u16, source: u16, size: OperandSize) -> u16 {
+    let result = destination & source & mask(size);
+    set_logic_flags(flags, result, size);
+    result
+}
+
+/// Bitwise OR of `destination` and `source`.
+pub fn or(flags: &mut Flags, destination: u16, source: u16, size: OperandSize) -> u16 {
+    let result = (destination | source) & mask(size);
+    set_logic_flags(flags, result, size);
+    result
+}
+
+/// Bitwise exclusive OR of `destination` and `source`.
+pub fn xor(flags: &mut Flags, destination: u16, source: u16, size: OperandSize) -> u16 {
+    let result = (destination ^ source) & mask(size);
+    set_logic_flags(flags, result, size);
+    result
+}
+
+/// Shifts or rotates `value` by `count` bits, for one of SHL, SHR, SAR, ROL, ROR, RCL or RCR.
+///
+/// The 8086 does not mask the count, so the operation is repeated `count` times
+/// and large counts shift every bit out. A count of zero leaves all flags untouched.
+///
+/// Flags follow the 8086 for every count, not just the documented count of one:
+/// * CF holds the last bit shifted or rotated out.
+/// * OF is `MSB(result) ^ CF` for left operations and the XOR of the two most
+///   significant bits of the result for right operations.
+/// * Shifts set SF, ZF and PF from the result. SHL sets AF from bit 4 of the
+///   result (the 8086 shifts left by adding the operand to itself), while SHR
+///   and SAR clear it.
+/// * Rotates only affect CF and OF.
+pub fn shift(
+    flags: &mut Flags,
+    operation: Mnemonic,
+    value: u16,
+    count: u8,
+    size: OperandSize,
+) -> u16 {
+    if count == 0 {
+        return value;
+    }
+
+    let mask = mask(size);
+    let sign_bit = sign_bit(size);
+    let mut result = value & mask;
+    let mut carry = flags.get_carry();
+    for _ in 0..count {
+        let msb = result & sign_bit != 0;
+        let lsb = result & 1 != 0;
+        result = match operation {
+            Mnemonic::Shl => {
+                carry = msb;
+                result << 1
+            }
+            Mnemonic::Shr => {
+                carry = lsb;
+                result >> 1
+            }
+            Mnemonic::Sar => {
+                carry = lsb;
+                (result >> 1) | (result & sign_bit)
+            }
+            Mnemonic::Rol => {
+                carry = msb;
+                (result << 1) | msb as u16
+            }
+            Mnemonic::Ror => {
+                carry = lsb;
+                (result >> 1) | if lsb { sign_bit } else { 0 }
+            }
+            Mnemonic::Rcl => {
+                let carry_in = carry;
+                carry = msb;
+                (result << 1) | carry_in as u16
+            }
+            Mnemonic::Rcr => {
+                let carry_in = carry;
+                carry = lsb;
+                (result >> 1) | if carry_in { sign_bit } else { 0 }
+            }
+            _ => unreachable!("{operation:?} is not a shift or rotate"),
+        } & mask;
+    }
+
+    let msb = result & sign_bit != 0;
+    flags.set_carry(carry);
+    match operation {
+        Mnemonic::Shl | Mnemonic::Rol | Mnemonic::Rcl => flags.set_overflow(msb ^ carry),
+        _ => flags.set_overflow(msb ^ (result & (sign_bit >> 1) != 0)),
+    }
+    match operation {
+        Mnemonic::Shl => flags.set_auxiliary_carry(result & 0x10 != 0),
+        Mnemonic::Shr | Mnemonic::Sar => flags.set_auxiliary_carry(false),
+        _ => return result,
+    }
+    set_result_flags(flags, result, size);
+    result
+}
+
+/// Sets the flags left behind by MUL and IMUL.
+///
+/// CF and OF are set when the high half of the product is significant. The
+/// remaining flags are documented as undefined; the 8086 multiply microcode
+/// finishes by passing the high half through the ALU, so SF, ZF and PF reflect
+/// it and AF is cleared.
+fn set_multiply_flags(flags: &mut Flags, high: u16, significant: bool, size: OperandSize) {
+    flags.set_carry(significant);
+    flags.set_overflow(significant);
+    flags.set_auxiliary_carry(false);
+    set_result_flags(flags, high, size);
+}
+
+/// Unsigned multiply. Returns the double-width product.
+pub fn mul(flags: &mut Flags, multiplicand: u16, multiplier: u16, size: OperandSize) -> u32 {
+    let mask = mask(size);
+    let product = (multiplicand & mask) as u32 * (multiplier & mask) as u32;
+    let high = (product >> bits(size)) as u16;
+    set_multiply_flags(flags, high, high != 0, size);
+    product
+}
+
+/// Signed multiply. Returns the double-width product.
+pub fn imul(flags: &mut Flags, multiplicand: u16, multiplier: u16, size: OperandSize) -> u32 {
+    let product = sign_extend(multiplicand, size) * sign_extend(multiplier, size);
+    let product = product as u32 & (((mask(size) as u32) << bits(size)) | mask(size) as u32);
+    let high = (product >> bits(size)) as u16;
+    let low = product as u16;
+    // The high half is significant unless it is just the sign extension of the low half.
+    let sign_extension = if low & sign_bit(size) != 0 {
+        mask(size)
+    } else {
+        0
+    };
+    set_multiply_flags(flags, high, high != sign_extension, size);
+    product
+}
+
+/// Unsigned divide of a double-width `dividend`.
+///
+/// Returns `(quotient, remainder)`, or `None` if the divisor is zero or the
+/// quotient does not fit in the operand size (a divide error).
+pub fn div(dividend: u32, divisor: u16, size: OperandSize) -> Option<(u16, u16)> {
+    let divisor = (divisor & mask(size)) as u32;
+    if divisor == 0 {
+        return None;
+    }
+    let quotient = dividend / divisor;
+    if quotient > mask(size) as u32 {
+        return None;
+    }
+    Some((quotient as u16, (dividend % divisor) as u16))
+}
+
+/// Signed divide of a double-width `dividend`, rounding the quotient toward zero.
+///
+/// Returns `(quotient, remainder)` with the remainder taking the sign of the
+/// dividend, or `None` on a divide error. Unlike later processors, the 8086
+/// also faults on the most negative quotient (-128 or -32768).
+pub fn idiv(dividend: u32, divisor: u16, size: OperandSize) -> Option<(u16, u16)> {
+    let divisor = sign_extend(divisor, size);
+    if divisor == 0 {
+        return None;
+    }
+    let dividend = match size {
+        OperandSize::Byte => dividend as u16 as i16 as i32,
+        OperandSize::Word => dividend as i32,
+    };
+    let limit = sign_bit(size) as i32 - 1;
+    let quotient = dividend.checked_div(divisor)?;
+    if !(-limit..=limit).contains(&quotient) {
+        return None;
+    }
+    let remainder = dividend % divisor;
+    let mask = mask(size);
+    Some((quotient as u16 & mask, remainder as u16 & mask))
+}
+
+/// Decimal adjust AL after a packed BCD addition.
+///
+/// When AF was set, the 8086 compares the original AL against 0x9F rather than
+/// 0x99 to decide on the high-digit correction. OF, SF, ZF and PF come from the
+/// correcting addition.
+pub fn daa(flags: &mut Flags, al: u8) -> u8 {
+    let (auxiliary_carry, carry) = (flags.get_auxiliary_carry(), flags.get_carry());
+    let mut correction = 0u8;
+    if al & 0x0F > 9 || auxiliary_carry {
+        correction |= 0x06;
+    }
+    let threshold = if auxiliary_carry { 0x9F } else { 0x99 };
+    if al > threshold || carry {
+        correction |= 0x60;
+    }
+    let result = al.wrapping_add(correction);
+
+    flags.set_auxiliary_carry(correction & 0x06 != 0);
+    flags.set_carry(correction & 0x60 != 0);
+    flags.set_overflow(!al & result & 0x80 != 0);
+    set_result_flags(flags, result as u16, OperandSize::Byte);
+    result
+}
+
+/// Decimal adjust AL after a packed BCD subtraction.
+///
+/// Uses the same 8086 thresholds as [`daa`]. OF, SF, ZF and PF come from the
+/// correcting subtraction.
+pub fn das(flags: &mut Flags, al: u8) -> u8 {
+    let (auxiliary_carry, carry) = (flags.get_auxiliary_carry(), flags.get_carry());
+    let mut correction = 0u8;
+    if al & 0x0F > 9 || auxiliary_carry {
+        correction |= 0x06;
+    }
+    let threshold = if auxiliary_carry { 0x9F } else { 0x99 };
+    if al > threshold || carry {
+        correction |= 0x60;
+    }
+    let result = al.wrapping_sub(correction);
+
+    flags.set_auxiliary_carry(correction & 0x06 != 0);
+    flags.set_carry(correction & 0x60 != 0);
+    flags.set_overflow(al & !result & 0x80 != 0);
+    set_result_flags(flags, result as u16, OperandSize::Byte);
+    result
+}
+
+/// ASCII adjust AX after an unpacked BCD addition.
+///
+/// The 8086 adds 6 to AL and 1 to AH separately, so the AL addition never
+/// carries into AH. OF, SF, ZF and PF come from the AL addition, before AL is
+/// masked to its low nibble.
+pub fn aaa(flags: &mut Flags, ax: u16) -> u16 {
+    let [al, ah] = ax.to_le_bytes();
+    let adjust = al & 0x0F > 9 || flags.get_auxiliary_carry();
+    let sum = al.wrapping_add(if adjust { 6 } else { 0 });
+    let ah = ah.wrapping_add(adjust as u8);
+
+    flags.set_overflow(!al & sum & 0x80 != 0);
+    set_result_flags(flags, sum as u16, OperandSize::Byte);
+    flags.set_auxiliary_carry(adjust);
+    flags.set_carry(adjust);
+    u16::from_le_bytes([sum & 0x0F, ah])
+}
+
+/// ASCII adjust AX after an unpacked BCD subtraction.
+///
+/// The counterpart of [`aaa`]: 6 is subtracted from AL and 1 from AH.
+pub fn aas(flags: &mut Flags, ax: u16) -> u16 {
+    let [al, ah] = ax.to_le_bytes();
+    let adjust = al & 0x0F > 9 || flags.get_auxiliary_carry();
+    let difference = al.wrapping_sub(if adjust { 6 } else { 0 });
+    let ah = ah.wrapping_sub(adjust as u8);
+
+    flags.set_overflow(al & !difference & 0x80 != 0);
+    set_result_flags(flags, difference as u16, OperandSize::Byte);
+    flags.set_auxiliary_carry(adjust);
+    flags.set_carry(adjust);
+    u16::from_le_bytes([difference & 0x0F, ah])
+}
+
+/// ASCII adjust AX after multiply: splits AL into `AL / base` (AH) and `AL % base` (AL).
+///
+/// Returns `None` for a base of zero, which raises a divide error. SF, ZF and
+/// PF reflect the new AL; CF, OF and AF are cleared.
+pub fn aam(flags: &mut Flags, al: u8, base: u8) -> Option<u16> {
+    if base == 0 {
+        return None;
+    }
+    let (quotient, remainder) = (al / base, al % base);
+    flags.set_carry(false);
+    flags.set_overflow(false);
+    flags.set_auxiliary_carry(false);
+    set_result_flags(flags, remainder as u16, OperandSize::Byte);
+    Some(u16::from_le_bytes([remainder, quotient]))
+}
+
+/// ASCII adjust AX before division: AL becomes `AH * base + AL` and AH is cleared.
+///
+/// All six status flags come from the final addition of AL and `AH * base`.
+pub fn aad(flags: &mut Flags, ax: u16, base: u8) -> u16 {
+    let [al, ah] = ax.to_le_bytes();
+    let product = ah.wrapping_mul(base);
+    add(flags, al as u16, product as u16, false, OperandSize::Byte)
+}
+
+/// Returns the number of bits in an operand of the given size.
+fn bits(size: OperandSize) -> u32 {
+    match size {
+        OperandSize::Byte => 8,
+        OperandSize::Word => 16,
+    }
+}
+
+/// Sign-extends an operand of the given size to 32 bits.
+fn sign_extend(value: u16, size: OperandSize) -> i32 {
+    match size {
+        OperandSize::Byte => value as u8 as i8 as i32,
+        OperandSize::Word => value as i16 as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reference flag values for a byte operation, computed with signed and
+    /// unsigned wide arithmetic instead of bit tricks.
+    struct Reference {
+        result: u8,
+        carry: bool,
+        auxiliary_carry: bool,
+        overflow: bool,
+    }
+
+    fn reference_add(a: u8, b: u8, carry: bool) -> Reference {
+        let unsigned = a as u32 + b as u32 + carry as u32;
+        let signed = a as i8 as i32 + b as i8 as i32 + carry as i32;
+        Reference {
+            result: unsigned as u8,
+            carry: unsigned > 0xFF,
+            auxiliary_carry: (a & 0x0F) as u32 + (b & 0x0F) as u32 + carry as u32 > 0x0F,
+            overflow: !(-128..=127).contains(&signed),
+        }
+    }
+
+    fn reference_sub(a: u8, b: u8, borrow: bool) -> Reference {
+        let unsigned = a as i32 - b as i32 - borrow as i32;
+        let signed = a as i8 as i32 - b as i8 as i32 - borrow as i32;
+        Reference {
+            result: unsigned as u8,
+            carry: unsigned < 0,
+            auxiliary_carry: ((a & 0x0F) as i32 - (b & 0x0F) as i32 - borrow as i32) < 0,
+            overflow: !(-128..=127).contains(&signed),
+        }
+    }
+
+    fn assert_flags(flags: &Flags, result: u16, expected: &Reference, context: &str) {
+        assert_eq!(result, expected.result as u16, "result of {context}");
+        assert_eq!(flags.get_carry(), expected.carry, "CF of {context}");
+        assert_eq!(
+            flags.get_auxiliary_carry(),
+            expected.auxiliary_carry,
+            "AF of {context}"
+        );
+        assert_eq!(flags.get_overflow(), expected.overflow, "OF of {context}");
+        assert_eq!(flags.get_zero(), expected.result == 0, "ZF of {context}");
+        assert_eq!(
+            flags.get_sign(),
+            expected.result & 0x80 != 0,
+            "SF of {context}"
+        );
+        assert_eq!(
+            flags.get_parity(),
+            expected.result.count_ones().is_multiple_of(2),
+            "PF of {context}"
+        );
+    }
+
+    #[test]
+    fn test_add_byte_exhaustive() {
+        for a in 0..=0xFFu8 {
+            for b in 0..=0xFFu8 {
+                for carry in [false, true] {
+                    let mut flags = Flags::default();
+                    let result = add(&mut flags, a as u16, b as u16, carry, OperandSize::Byte);
+                    let context = format!("{a:#04x} + {b:#04x} + {carry}");
+                    assert_flags(&flags, result, &reference_add(a, b, carry), &context);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sub_byte_exhaustive() {
+        for a in 0..=0xFFu8 {
+            for b in 0..=0xFFu8 {
+                for borrow in [false, true] {
+                    let mut flags = Flags::default();
+                    let result = sub(&mut flags, a as u16, b as u16, borrow, OperandSize::Byte);
+                    let context = format!("{a:#04x} - {b:#04x} - {borrow}");
+                    assert_flags(&flags, result, &reference_sub(a, b, borrow), &context);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_inc_and_dec_byte_exhaustive_preserve_carry() {
+        for a in 0..=0xFFu8 {
+            for carry in [false, true] {
+                let mut flags = Flags::default();
+                flags.set_carry(carry);
+                let result = inc(&mut flags, a as u16, OperandSize::Byte);
+                let mut expected = reference_add(a, 1, false);
+                expected.carry = carry;
+                assert_flags(&flags, result, &expected, &format!("inc {a:#04x}"));
+
+                let mut flags = Flags::default();
+                flags.set_carry(carry);
+                let result = dec(&mut flags, a as u16, OperandSize::Byte);
+                let mut expected = reference_sub(a, 1, false);
+                expected.carry = carry;
+                assert_flags(&flags, result, &expected, &format!("dec {a:#04x}"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_neg_byte_exhaustive() {
+        for a in 0..=0xFFu8 {
+            let mut flags = Flags::default();
+            let result = neg(&mut flags, a as u16, OperandSize::Byte);
+            let expected = reference_sub(0, a, false);
+            assert_eq!(expected.carry, a != 0);
+            assert_flags(&flags, result, &expected, &format!("neg {a:#04x}"));
+        }
+    }
+
+    #[test]
+    fn test_add_word() {
+        let mut flags = Flags::default();
+        assert_eq!(
+            add(&mut flags, 0x7FFF, 0x0001, false, OperandSize::Word),
+            0x8000
+        );
+        assert!(flags.get_overflow());
+        assert!(flags.get_sign());
+        assert!(!flags.get_carry());
+        assert!(flags.get_auxiliary_carry());
+
+        assert_eq!(
+            add(&mut flags, 0xFFFF, 0x0000, true, OperandSize::Word),
+            0x0000
+        );
+        assert!(flags.get_carry());
+        assert!(flags.get_zero());
+        assert!(!flags.get_overflow());
+    }
+
+    #[test]
+    fn test_sub_word() {
+        let mut flags = Flags::default();
+        assert_eq!(
+            sub(&mut flags, 0x8000, 0x0001, false, OperandSize::Word),
+            0x7FFF
+        );
+        assert!(flags.get_overflow());
+        assert!(!flags.get_carry());
+        assert!(flags.get_auxiliary_carry());
+
+        assert_eq!(
+            sub(&mut flags, 0x0001, 0x0001, true, OperandSize::Word),
+            0xFFFF
+        );
+        assert!(flags.get_carry());
+        assert!(flags.get_sign());
+        // Parity only considers the low byte.
+        assert!(flags.get_parity());
+    }
+
+    #[test]
+    fn test_neg_word() {
+        let mut flags = Flags::default();
+        assert_eq!(neg(&mut flags, 0x8000, OperandSize::Word), 0x8000);
+        assert!(flags.get_overflow());
+        assert!(flags.get_carry());
+        assert_eq!(neg(&mut flags, 0x0000, OperandSize::Word), 0x0000);
+        assert!(!flags.get_carry());
+    }
+
+    #[test]
+    fn test_logic_clears_carry_overflow_and_auxiliary_carry() {
+        let mut flags = Flags::default();
+        flags.set_carry(true);
+        flags.set_overflow(true);
+        flags.set_auxiliary_carry(true);
+        assert_eq!(and(&mut flags, 0xF0F0, 0x0FF0, OperandSize::Word), 0x00F0);
+        assert!(!flags.get_carry());
+        assert!(!flags.get_overflow());
+        assert!(!flags.get_auxiliary_carry());
+        assert!(flags.get_parity());
+
+        assert_eq!(or(&mut flags, 0x80, 0x01, OperandSize::Byte), 0x81);
+        assert!(flags.get_sign());
+        assert_eq!(xor(&mut flags, 0x5A, 0x5A, OperandSize::Byte), 0x00);
+        assert!(flags.get_zero());
+    }
+
+    /// Shifts a byte one bit at a time with plain integer arithmetic.
+    fn reference_shift(operation: Mnemonic, value: u8, count: u8, carry: bool) -> (u8, bool) {
+        let (mut result, mut carry) = (value, carry);
+        for _ in 0..count {
+            let (next, out) = match operation {
+                Mnemonic::Shl => (result.wrapping_mul(2), result >= 0x80),
+                Mnemonic::Shr => (result / 2, result % 2 == 1),
+                Mnemonic::Sar => (((result as i8) >> 1) as u8, result % 2 == 1),
+                Mnemonic::Rol => (result.rotate_left(1), result >= 0x80),
+                Mnemonic::Ror => (result.rotate_right(1), result % 2 == 1),
+                Mnemonic::Rcl => (result.wrapping_mul(2) + carry as u8, result >= 0x80),
+                _ => (result / 2 + if carry { 0x80 } else { 0 }, result % 2 == 1),
+            };
+            result = next;
+            carry = out;
+        }
+        (result, carry)
+    }
+
+    #[test]
+    fn test_shift_byte_exhaustive() {
+        let operations = [
+            Mnemonic::Shl,
+            Mnemonic::Shr,
+            Mnemonic::Sar,
+            Mnemonic::Rol,
+            Mnemonic::Ror,
+            Mnemonic::Rcl,
+            Mnemonic::Rcr,
+        ];
+        for operation in operations {
+            for value in 0..=0xFFu8 {
+                for count in [1, 2, 7, 8, 9, 17, 255] {
+                    for carry in [false, true] {
+                        let mut flags = Flags::default();
+                        flags.set_carry(carry);
+                        let result = shift(
+                            &mut flags,
+                            operation,
+                            value as u16,
+                            count,
+                            OperandSize::Byte,
+                        );
+                        let (expected, expected_carry) =
+                            reference_shift(operation, value, count, carry);
+                        let context = format!("{operation:?} {value:#04x}, {count} (CF={carry})");
+                        assert_eq!(result, expected as u16, "result of {context}");
+                        assert_eq!(flags.get_carry(), expected_carry, "CF of {context}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_shift_by_zero_changes_nothing() {
+        let mut flags = Flags::default();
+        flags.set_carry(true);
+        flags.set_overflow(true);
+        assert_eq!(
+            shift(&mut flags, Mnemonic::Shl, 0x81, 0, OperandSize::Byte),
+            0x81
+        );
+        assert!(flags.get_carry());
+        assert!(flags.get_overflow());
+        assert!(!flags.get_zero());
+    }
+
+    #[test]
+    fn test_shift_count_is_not_masked() {
+        // A count of 32 would be masked to 0 on later processors.
+        let mut flags = Flags::default();
+        assert_eq!(
+            shift(&mut flags, Mnemonic::Shl, 0xFFFF, 32, OperandSize::Word),
+            0
+        );
+        assert!(flags.get_zero());
+        assert!(!flags.get_carry());
+
+        assert_eq!(
+            shift(&mut flags, Mnemonic::Sar, 0x8000, 32, OperandSize::Word),
+            0xFFFF
+        );
+        assert!(flags.get_carry());
+    }
+
+    #[test]
+    fn test_shift_overflow() {
+        let mut flags = Flags::default();
+        // SHL: OF = MSB(result) ^ CF
+        shift(&mut flags, Mnemonic::Shl, 0x40, 1, OperandSize::Byte);
+        assert!(flags.get_overflow());
+        shift(&mut flags, Mnemonic::Shl, 0xC0, 1, OperandSize::Byte);
+        assert!(!flags.get_overflow());
+        // Multi-bit counts use the same rule on the final result.
+        shift(&mut flags, Mnemonic::Shl, 0x20, 2, OperandSize::Byte);
+        assert!(flags.get_overflow());
+
+        // SHR: OF = original MSB for a count of 1
+        shift(&mut flags, Mnemonic::Shr, 0x80, 1, OperandSize::Byte);
+        assert!(flags.get_overflow());
+        shift(&mut flags, Mnemonic::Shr, 0x80, 2, OperandSize::Byte);
+        assert!(!flags.get_overflow());
+
+        // SAR never overflows
+        shift(&mut flags, Mnemonic::Sar, 0x8000, 1, OperandSize::Word);
+        assert!(!flags.get_overflow());
+
+        // ROR/RCR: OF = XOR of the two most significant bits of the result
+        shift(&mut flags, Mnemonic::Ror, 0x01, 1, OperandSize::Byte);
+        assert!(flags.get_overflow());
+        flags.set_carry(true);
+        shift(&mut flags, Mnemonic::Rcr, 0x80, 1, OperandSize::Byte);
+        assert!(!flags.get_overflow());
+    }
+
+    #[test]
+    fn test_shift_auxiliary_carry_and_result_flags() {
+        let mut flags = Flags::default();
+        shift(&mut flags, Mnemonic::Shl, 0x08, 1, OperandSize::Byte);
+        assert!(flags.get_auxiliary_carry());
+        shift(&mut flags, Mnemonic::Shr, 0x20, 1, OperandSize::Byte);
+        assert!(!flags.get_auxiliary_carry());
+        assert!(!flags.get_parity());
+
+        // Rotates leave SF, ZF, PF and AF alone.
+        flags.set_auxiliary_carry(true);
+        flags.set_zero(true);
+        shift(&mut flags, Mnemonic::Rol, 0x80, 1, OperandSize::Byte);
+        assert!(flags.get_auxiliary_carry());
+        assert!(flags.get_zero());
+        assert!(flags.get_carry());
+    }
+
+    #[test]
+    fn test_mul_byte_exhaustive() {
+        for a in 0..=0xFFu8 {
+            for b in 0..=0xFFu8 {
+                let mut flags = Flags::default();
+                let product = mul(&mut flags, a as u16, b as u16, OperandSize::Byte);
+                assert_eq!(product, a as u32 * b as u32);
+                let high = (product >> 8) as u8;
+                assert_eq!(flags.get_carry(), high != 0, "CF of {a} * {b}");
+                assert_eq!(flags.get_overflow(), high != 0, "OF of {a} * {b}");
+                assert_eq!(flags.get_zero(), high == 0, "ZF of {a} * {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_imul_byte_exhaustive() {
+        for a in 0..=0xFFu8 {
+            for b in 0..=0xFFu8 {
+                let mut flags = Flags::default();
+                let product = imul(&mut flags, a as u16, b as u16, OperandSize::Byte);
+                let expected = a as i8 as i16 * b as i8 as i16;
+                assert_eq!(product as u16, expected as u16, "{a} * {b}");
+                let fits = (-128..=127).contains(&expected);
+                assert_eq!(flags.get_carry(), !fits, "CF of {a} * {b}");
+                assert_eq!(flags.get_overflow(), !fits, "OF of {a} * {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_mul_and_imul_word() {
+        let mut flags = Flags::default();
+        assert_eq!(
+            mul(&mut flags, 0xFFFF, 0xFFFF, OperandSize::Word),
+            0xFFFE_0001
+        );
+        assert!(flags.get_carry());
+        assert_eq!(
+            imul(&mut flags, 0xFFFF, 0xFFFF, OperandSize::Word),
+            0x0000_0001
+        );
+        assert!(!flags.get_carry());
+        assert_eq!(
+            imul(&mut flags, 0x8000, 0x0002, OperandSize::Word),
+            0xFFFF_0000
+        );
+        assert!(flags.get_overflow());
+        assert!(flags.get_sign());
+    }
+
+    #[test]
+    fn test_div_byte_exhaustive() {
+        for dividend in (0..=0xFFFFu32).step_by(7) {
+            for divisor in 0..=0xFFu16 {
+                let expected = if divisor == 0 || dividend / divisor as u32 > 0xFF {
+                    None
+                } else {
+                    Some((
+                        (dividend / divisor as u32) as u16,
+                        (dividend % divisor as u32) as u16,
+                    ))
+                };
+                assert_eq!(div(dividend, divisor, OperandSize::Byte), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_idiv() {
+        // -7 / 2 = -3 remainder -1
+        assert_eq!(idiv(0xFFF9, 0x02, OperandSize::Byte), Some((0xFD, 0xFF)));
+        // 7 / -2 = -3 remainder 1
+        assert_eq!(idiv(0x0007, 0xFE, OperandSize::Byte), Some((0xFD, 0x01)));
+        // 127 fits, 128 does not
+        assert_eq!(idiv(0x00FE, 0x02, OperandSize::Byte), Some((0x7F, 0x00)));
+        assert_eq!(idiv(0x0100, 0x02, OperandSize::Byte), None);
+        // -128 faults on the 8086
+        assert_eq!(idiv(0xFF00, 0x02, OperandSize::Byte), None);
+        assert_eq!(idiv(0x8000_0000, 0xFFFF, OperandSize::Word), None);
+        assert_eq!(idiv(0x1234, 0x00, OperandSize::Byte), None);
+        assert_eq!(
+            idiv(0xFFFF_0000, 0x0100, OperandSize::Word),
+            Some((0xFF00, 0x0000))
+        );
+    }
+
+    fn to_bcd(value: u8) -> u8 {
+        (value / 10) << 4 | (value % 10)
+    }
+
+    #[test]
+    fn test_daa_after_bcd_addition() {
+        for a in 0..100u8 {
+            for b in 0..100u8 {
+                for carry in [false, true] {
+                    let mut flags = Flags::default();
+                    let sum = add(
+                        &mut flags,
+                        to_bcd(a) as u16,
+                        to_bcd(b) as u16,
+                        carry,
+                        OperandSize::Byte,
+                    );
+                    let result = daa(&mut flags, sum as u8);
+                    let decimal = a as u16 + b as u16 + carry as u16;
+                    assert_eq!(result, to_bcd((decimal % 100) as u8), "{a} + {b} + {carry}");
+                    assert_eq!(
+                        flags.get_carry(),
+                        decimal >= 100,
+                        "CF of {a} + {b} + {carry}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_das_after_bcd_subtraction() {
+        for a in 0..100u8 {
+            for b in 0..100u8 {
+                for borrow in [false, true] {
+                    let mut flags = Flags::default();
+                    let difference = sub(
+                        &mut flags,
+                        to_bcd(a) as u16,
+                        to_bcd(b) as u16,
+                        borrow,
+                        OperandSize::Byte,
+                    );
+                    let result = das(&mut flags, difference as u8);
+                    let decimal = a as i16 - b as i16 - borrow as i16;
+                    assert_eq!(
+                        result,
+                        to_bcd(decimal.rem_euclid(100) as u8),
+                        "{a} - {b} - {borrow}"
+                    );
+                    assert_eq!(flags.get_carry(), decimal < 0, "CF of {a} - {b} - {borrow}");
+                }
+            }
+        }
+    }
+
+    /// The 8086 DAA/DAS decision table, written out as (AL, AF, CF) -> (correction, AF, CF).
+    fn reference_decimal_correction(
+        al: u8,
+        auxiliary_carry: bool,
+        carry: bool,
+    ) -> (u8, bool, bool) {
+        let low = al & 0x0F > 9 || auxiliary_carry;
+        let high = match (auxiliary_carry, carry) {
+            (_, true) => true,
+            (false, false) => al >= 0x9A,
+            (true, false) => al >= 0xA0,
+        };
+        let correction = if low { 0x06 } else { 0 } + if high { 0x60 } else { 0 };
+        (correction, low, high)
+    }
+
+    #[test]
+    fn test_daa_and_das_byte_exhaustive() {
+        for al in 0..=0xFFu8 {
+            for auxiliary_carry in [false, true] {
+                for carry in [false, true] {
+                    let (correction, expected_af, expected_cf) =
+                        reference_decimal_correction(al, auxiliary_carry, carry);
+                    let context = format!("{al:#04x} AF={auxiliary_carry} CF={carry}");
+
+                    let mut flags = Flags::default();
+                    flags.set_auxiliary_carry(auxiliary_carry);
+                    flags.set_carry(carry);
+                    let result = daa(&mut flags, al);
+                    let expected = al.wrapping_add(correction);
+                    assert_eq!(result, expected, "daa {context}");
+                    assert_eq!(
+                        flags.get_auxiliary_carry(),
+                        expected_af,
+                        "AF of daa {context}"
+                    );
+                    assert_eq!(flags.get_carry(), expected_cf, "CF of daa {context}");
+                    assert_eq!(flags.get_zero(), expected == 0, "ZF of daa {context}");
+                    assert_eq!(flags.get_sign(), expected >= 0x80, "SF of daa {context}");
+                    assert_eq!(
+                        flags.get_overflow(),
+                        al < 0x80 && expected >= 0x80,
+                        "OF of daa {context}"
+                    );
+
+                    let mut flags = Flags::default();
+                    flags.set_auxiliary_carry(auxiliary_carry);
+                    flags.set_carry(carry);
+                    let result = das(&mut flags, al);
+                    let expected = al.wrapping_sub(correction);
+                    assert_eq!(result, expected, "das {context}");
+                    assert_eq!(
+                        flags.get_auxiliary_carry(),
+                        expected_af,
+                        "AF of das {context}"
+                    );
+                    assert_eq!(flags.get_carry(), expected_cf, "CF of das {context}");
+                    assert_eq!(
+                        flags.get_overflow(),
+                        al >= 0x80 && expected < 0x80,
+                        "OF of das {context}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_aaa_and_aas_byte_exhaustive() {
+        for al in 0..=0xFFu8 {
+            for auxiliary_carry in [false, true] {
+                let adjust = al & 0x0F > 9 || auxiliary_carry;
+                let context = format!("{al:#04x} AF={auxiliary_carry}");
+
+                let mut flags = Flags::default();
+                flags.set_auxiliary_carry(auxiliary_carry);
+                let result = aaa(&mut flags, 0x1200 | al as u16);
+                let sum = al.wrapping_add(if adjust { 6 } else { 0 });
+                let ah = if adjust { 0x13 } else { 0x12 };
+                assert_eq!(
+                    result,
+                    (ah as u16) << 8 | (sum & 0x0F) as u16,
+                    "aaa {context}"
+                );
+                assert_eq!(flags.get_carry(), adjust, "CF of aaa {context}");
+                assert_eq!(flags.get_auxiliary_carry(), adjust, "AF of aaa {context}");
+                assert_eq!(flags.get_zero(), sum == 0, "ZF of aaa {context}");
+
+                let mut flags = Flags::default();
+                flags.set_auxiliary_carry(auxiliary_carry);
+                let result = aas(&mut flags, 0x1200 | al as u16);
+                let difference = al.wrapping_sub(if adjust { 6 } else { 0 });
+                let ah = if adjust { 0x11 } else { 0x12 };
+                assert_eq!(
+                    result,
+                    (ah as u16) << 8 | (difference & 0x0F) as u16,
+                    "aas {context}"
+                );
+                assert_eq!(flags.get_carry(), adjust, "CF of aas {context}");
+                assert_eq!(flags.get_sign(), difference >= 0x80, "SF of aas {context}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_aaa_does_not_carry_from_al_into_ah() {
+        let mut flags = Flags::default();
+        // 0xFA + 6 wraps AL; on the 8086 AH is only incremented once.
+        assert_eq!(aaa(&mut flags, 0x00FA), 0x0100);
+    }
+
+    #[test]
+    fn test_aam_byte_exhaustive() {
+        for al in 0..=0xFFu8 {
+            for base in [1u8, 7, 10, 16, 0xFF] {
+                let mut flags = Flags::default();
+                let result = aam(&mut flags, al, base).unwrap();
+                assert_eq!(result >> 8, (al / base) as u16, "AH of aam {al}, {base}");
+                assert_eq!(result & 0xFF, (al % base) as u16, "AL of aam {al}, {base}");
+                assert_eq!(flags.get_zero(), al % base == 0);
+            }
+            assert_eq!(aam(&mut Flags::default(), al, 0), None);
+        }
+    }
+
+    #[test]
+    fn test_aad_byte_exhaustive() {
+        for ah in 0..=0xFFu8 {
+            for al in (0..=0xFFu8).step_by(3) {
+                for base in [10u8, 16, 0xFF] {
+                    let mut flags = Flags::default();
+                    let result = aad(&mut flags, u16::from_le_bytes([al, ah]), base);
+                    let expected = (al as u16 + ah as u16 * base as u16) as u8;
+                    assert_eq!(result, expected as u16, "aad {ah}:{al}, {base}");
+                    assert_eq!(flags.get_sign(), expected >= 0x80);
+                    assert_eq!(
+                        flags.get_carry(),
+                        al as u16 + (ah.wrapping_mul(base)) as u16 > 0xFF
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,45 @@
+use std::collections::VecDeque;
+
 use super::bus;
+use super::decode::SegmentRegister;
+use super::memory::{Memory, physical_address};
+use super::timing;
 // use crate::bus::AddressBus;
 
+/// Value of CS after a reset; with IP = 0 the first instruction is fetched
+/// from physical address FFFF0h.
+pub const RESET_CODE_SEGMENT: u16 = 0xFFFF;
+
+/// The processor variant, which decides the width of the external data bus
+/// and with it the size of the prefetch queue.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    /// 16-bit data bus and a 6-byte queue.
+    #[default]
+    Intel8086,
+    /// 8-bit data bus and a 4-byte queue.
+    Intel8088,
+}
+
+impl Model {
+    /// Returns the number of bytes the prefetch queue holds.
+    pub fn queue_size(self) -> usize {
+        match self {
+            Model::Intel8086 => 6,
+            Model::Intel8088 => 4,
+        }
+    }
+
+    /// Returns the number of bytes fetched per bus cycle; the BIU prefetches
+    /// whenever this many bytes of the queue are free.
+    pub fn fetch_width(self) -> usize {
+        match self {
+            Model::Intel8086 => 2,
+            Model::Intel8088 => 1,
+        }
+    }
+}
+
 /// Represents the Bus Interface Unit (BIU) of the CPU, which is responsible for interfacing with the system bus.
 #[derive(Debug)]
 pub struct BusInterfaceUnit<'a> {
@@ -15,12 +54,17 @@ pub struct BusInterfaceUnit<'a> {
     /// Instruction Pointer; points to the next instruction to be executed
     ip: u16,
 
-    /// Queue of bytes to be read from memory
-    instruction_queue: Vec<u8>,
+    /// Queue of bytes prefetched from memory, starting at CS:IP
+    instruction_queue: VecDeque<u8>,
+    model: Model,
+    /// Clocks spent on extra bus cycles since they were last taken
+    bus_clocks: u32,
     bus: &'a mut bus::AddressBus,
 }
 
 impl<'a> BusInterfaceUnit<'a> {
+    /// Creates an 8086 BIU. Bytes of `instruction_queue` beyond the queue
+    /// size are dropped.
     pub fn new(
         es: u16,
         cs: u16,
@@ -30,17 +74,33 @@ impl<'a> BusInterfaceUnit<'a> {
         instruction_queue: Vec<u8>,
         bus: &'a mut bus::AddressBus,
     ) -> Self {
+        let model = Model::default();
+        let mut instruction_queue = instruction_queue;
+        instruction_queue.truncate(model.queue_size());
         Self {
             es,
             cs,
             ss,
             ds,
             ip,
-            instruction_queue,
+            instruction_queue: instruction_queue.into(),
+            model,
+            bus_clocks: 0,
             bus,
         }
     }
 
+    /// Puts the BIU into the 8086 power-on state: CS = FFFFh, IP, DS, SS and
+    /// ES = 0000h, and an empty instruction queue.
+    pub fn reset(&mut self) {
+        self.es = 0;
+        self.cs = RESET_CODE_SEGMENT;
+        self.ss = 0;
+        self.ds = 0;
+        self.ip = 0;
+        self.flush_instruction_queue();
+    }
+
     pub fn set_extra_segment_address(&mut self, value: u16) {
         self.es = value;
     }
@@ -48,8 +108,10 @@ impl<'a> BusInterfaceUnit<'a> {
         self.es
     }
 
+    /// Sets CS. The prefetched bytes belong to the old segment and are discarded.
     pub fn set_code_segment_address(&mut self, value: u16) {
         self.cs = value;
+        self.flush_instruction_queue();
     }
     pub fn get_code_segment_address(&self) -> u16 {
         self.cs
@@ -69,45 +131,230 @@ impl<'a> BusInterfaceUnit<'a> {
         self.ds
     }
 
+    /// Returns the value of the given segment register.
+    pub fn get_segment(&self, segment: SegmentRegister) -> u16 {
+        match segment {
+            SegmentRegister::ES => self.es,
+            SegmentRegister::CS => self.cs,
+            SegmentRegister::SS => self.ss,
+            SegmentRegister::DS => self.ds,
+        }
+    }
+    /// Sets the value of the given segment register.
+    pub fn set_segment(&mut self, segment: SegmentRegister, value: u16) {
+        match segment {
+            SegmentRegister::ES => self.es = value,
+            SegmentRegister::CS => self.set_code_segment_address(value),
+            SegmentRegister::SS => self.ss = value,
+            SegmentRegister::DS => self.ds = value,
+        }
+    }
+
+    /// Sets IP. As on a jump, the prefetched bytes are discarded.
     pub fn set_instruction_pointer(&mut self, value: u16) {
         self.ip = value;
+        self.flush_instruction_queue();
     }
     pub fn get_instruction_pointer(&self) -> u16 {
         self.ip
     }
 
-    pub fn push_instruction(&mut self, instruction: u8) {
-        self.instruction_queue.push(instruction);
+    pub fn get_model(&self) -> Model {
+        self.model
+    }
+    /// Switches between the 8086 and 8088 bus, flushing the instruction queue.
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+        self.flush_instruction_queue();
     }
+
+    /// Appends a byte to the back of the instruction queue. Returns false,
+    /// dropping the byte, if the queue is full.
+    pub fn push_instruction(&mut self, instruction: u8) -> bool {
+        if self.instruction_queue.len() >= self.model.queue_size() {
+            return false;
+        }
+        self.instruction_queue.push_back(instruction);
+        true
+    }
+    /// Removes the oldest byte from the front of the instruction queue.
     pub fn pop_instruction(&mut self) -> Option<u8> {
-        self.instruction_queue.pop()
+        self.instruction_queue.pop_front()
+    }
+    /// Discards all prefetched instruction bytes, as happens on every jump.
+    pub fn flush_instruction_queue(&mut self) {
+        self.instruction_queue.clear();
+    }
+    /// Returns the number of bytes currently in the instruction queue.
+    pub fn get_queue_length(&self) -> usize {
+        self.instruction_queue.len()
     }
 
     pub fn get_fetch_address(&self) -> u32 {
-        ((self.cs as u32) << 4) + self.ip as u32
+        physical_address(self.cs, self.ip)
+    }
+
+    /// Prefetches instruction bytes until fewer than a bus cycle's worth of
+    /// the queue is free.
+    ///
+    /// Bytes are read from CS at the offset following the last queued byte.
+    /// The 8086 fetches aligned words, so from an odd offset it fetches a
+    /// single byte first.
+    pub fn fill_instruction_queue(&mut self) {
+        let size = self.model.queue_size();
+        let width = self.model.fetch_width();
+        while size.saturating_sub(self.instruction_queue.len()) >= width {
+            let offset = self.ip.wrapping_add(self.instruction_queue.len() as u16);
+            let count = if offset % 2 == 1 { 1 } else { width };
+            let byte = self.read_byte(physical_address(self.cs, offset));
+            self.instruction_queue.push_back(byte);
+            if count == 2 {
+                let address = physical_address(self.cs, offset.wrapping_add(1));
+                let byte = self.in_same_cycle(|biu| biu.read_byte(address));
+                self.instruction_queue.push_back(byte);
+            }
+        }
+    }
+
+    /// Takes the next instruction byte from the queue and advances the
+    /// instruction pointer. An empty queue is filled from CS:IP first.
+    pub fn fetch_instruction_byte(&mut self) -> u8 {
+        if self.instruction_queue.is_empty() {
+            self.fill_instruction_queue();
+        }
+        let byte = self
+            .instruction_queue
+            .pop_front()
+            .expect("a filled instruction queue is never empty");
+        self.ip = self.ip.wrapping_add(1);
+        byte
+    }
+
+    /// Reads a byte from memory at the given physical address.
+    pub fn read_byte(&mut self, address: u32) -> u8 {
+        self.bus.set_address(address);
+        self.bus.read()
+    }
+
+    /// Writes a byte to memory at the given physical address.
+    pub fn write_byte(&mut self, address: u32, value: u8) {
+        self.bus.set_address(address);
+        self.bus.write(value);
+    }
+
+    /// Returns the RAM behind the bus, so loaders can fill it directly rather
+    /// than with one bus cycle per byte.
+    pub fn get_memory_mut(&mut self) -> &mut Memory {
+        self.bus.get_memory_mut()
+    }
+
+    /// Reads a little-endian word at `segment:offset`. The high byte's offset
+    /// wraps within the segment.
+    pub fn read_word(&mut self, segment: u16, offset: u16) -> u16 {
+        let split = self.charge_word_transfer(segment, offset);
+        let low = self.read_byte(physical_address(segment, offset));
+        let address = physical_address(segment, offset.wrapping_add(1));
+        let high = if split {
+            self.read_byte(address)
+        } else {
+            self.in_same_cycle(|biu| biu.read_byte(address))
+        };
+        u16::from_le_bytes([low, high])
+    }
+
+    /// Writes a little-endian word at `segment:offset`. The high byte's offset
+    /// wraps within the segment.
+    pub fn write_word(&mut self, segment: u16, offset: u16, value: u16) {
+        let split = self.charge_word_transfer(segment, offset);
+        let [low, high] = value.to_le_bytes();
+        self.write_byte(physical_address(segment, offset), low);
+        let address = physical_address(segment, offset.wrapping_add(1));
+        if split {
+            self.write_byte(address, high);
+        } else {
+            self.in_same_cycle(|biu| biu.write_byte(address, high));
+        }
+    }
+
+    /// Reads a byte from an I/O port.
+    pub fn read_port_byte(&mut self, port: u16) -> u8 {
+        self.bus.read_io(port)
+    }
+
+    /// Writes a byte to an I/O port.
+    pub fn write_port_byte(&mut self, port: u16, value: u8) {
+        self.bus.write_io(port, value);
+    }
+
+    /// Reads a little-endian word from ports `port` and `port + 1`.
+    pub fn read_port_word(&mut self, port: u16) -> u16 {
+        self.charge_port_word_transfer(port);
+        self.bus.read_io_word(port)
+    }
+
+    /// Writes a little-endian word to ports `port` and `port + 1`.
+    pub fn write_port_word(&mut self, port: u16, value: u16) {
+        self.charge_port_word_transfer(port);
+        self.bus.write_io_word(port, value);
+    }
+
+    /// Returns the clocks spent on extra bus cycles and device wait states
+    /// since the last call, and resets the count.
+    pub fn take_bus_clocks(&mut self) -> u32 {
+        std::mem::take(&mut self.bus_clocks) + self.bus.take_wait_states()
+    }
+
+    /// Charges the second bus cycle a word transfer needs when it cannot be
+    /// done in one: at an odd address on the 8086, and always on the 8088.
+    /// Returns true if the transfer takes two bus cycles.
+    fn charge_word_transfer(&mut self, segment: u16, offset: u16) -> bool {
+        self.charge_transfer_at(physical_address(segment, offset))
+    }
+
+    /// As [`Self::charge_word_transfer`], for a word transfer to the I/O space.
+    fn charge_port_word_transfer(&mut self, port: u16) {
+        self.charge_transfer_at(port as u32);
+    }
+
+    fn charge_transfer_at(&mut self, address: u32) -> bool {
+        let split = address % 2 == 1 || self.model == Model::Intel8088;
+        if split {
+            self.bus_clocks += timing::EXTRA_BUS_CYCLE_CLOCKS;
+        }
+        split
+    }
+
+    /// Performs the high-byte half of a word transfer that shares the bus
+    /// cycle of its low byte, so the device's wait states are charged once
+    /// per cycle rather than once per byte.
+    fn in_same_cycle<T>(&mut self, access: impl FnOnce(&mut Self) -> T) -> T {
+        self.bus_clocks += self.bus.take_wait_states();
+        let value = access(self);
+        self.bus.take_wait_states();
+        value
     }
 
     pub fn get_stack_address(&self, sp_offset: u16) -> u32 {
-        ((self.ss as u32) << 4) + sp_offset as u32
+        physical_address(self.ss, sp_offset)
     }
 
     pub fn get_string_source_address(&self, si_offset: u16, alt_base: Option<u16>) -> u32 {
         let base = alt_base.unwrap_or(self.ds);
-        ((base as u32) << 4) + si_offset as u32
+        physical_address(base, si_offset)
     }
 
     pub fn get_string_destination_address(&self, di_offset: u16) -> u32 {
-        ((self.es as u32) << 4) + di_offset as u32
+        physical_address(self.es, di_offset)
     }
 
     pub fn get_data_address(&self, eu_offset: u16, alt_base: Option<u16>) -> u32 {
         let base = alt_base.unwrap_or(self.ds);
-        ((base as u32) << 4) + eu_offset as u32
+        physical_address(base, eu_offset)
     }
 
     pub fn get_bp_address(&self, eu_bp_offset: u16, alt_base: Option<u16>) -> u32 {
         let base = alt_base.unwrap_or(self.ss);
-        ((base as u32) << 4) + eu_bp_offset as u32
+        physical_address(base, eu_bp_offset)
     }
 }
 
@@ -163,6 +410,230 @@ mod tests {
         assert_eq!(biu.pop_instruction(), Some(0x42));
     }
 
+    #[test]
+    fn test_reset() {
+        let mut bus = bus::AddressBus::new();
+        let mut biu =
+            BusInterfaceUnit::new(0x1111, 0x2222, 0x3333, 0x4444, 0x5555, vec![0x90], &mut bus);
+        biu.reset();
+        assert_eq!(biu.get_code_segment_address(), 0xFFFF);
+        assert_eq!(biu.get_instruction_pointer(), 0x0000);
+        assert_eq!(biu.get_data_segment_address(), 0x0000);
+        assert_eq!(biu.get_stack_segment_address(), 0x0000);
+        assert_eq!(biu.get_extra_segment_address(), 0x0000);
+        assert_eq!(biu.get_fetch_address(), 0xFFFF0);
+        assert_eq!(biu.pop_instruction(), None);
+    }
+
+    #[test]
+    fn test_set_and_get_segment() {
+        let mut bus = bus::AddressBus::new();
+        let mut biu = BusInterfaceUnit::new(0, 0, 0, 0, 0, vec![], &mut bus);
+        biu.set_segment(SegmentRegister::SS, 0x2468);
+        assert_eq!(biu.get_segment(SegmentRegister::SS), 0x2468);
+        assert_eq!(biu.get_stack_segment_address(), 0x2468);
+    }
+
+    #[test]
+    fn test_read_and_write_byte() {
+        let mut bus = bus::AddressBus::new();
+        let mut biu = BusInterfaceUnit::new(0, 0, 0, 0, 0, vec![], &mut bus);
+        biu.write_byte(0x12345, 0xA5);
+        assert_eq!(biu.read_byte(0x12345), 0xA5);
+    }
+
+    #[test]
+    fn test_instruction_queue_is_fifo_and_bounded() {
+        let mut bus = bus::AddressBus::new();
+        let mut biu = BusInterfaceUnit::new(0, 0, 0, 0, 0, vec![], &mut bus);
+        for byte in 1..=6 {
+            assert!(biu.push_instruction(byte));
+        }
+        assert!(!biu.push_instruction(7));
+        assert_eq!(biu.pop_instruction(), Some(1));
+        assert_eq!(biu.pop_instruction(), Some(2));
+
+        biu.set_model(Model::Intel8088);
+        for byte in 1..=4 {
+            assert!(biu.push_instruction(byte));
+        }
+        assert!(!biu.push_instruction(5));
+    }
+
+    #[test]
+    fn test_fill_instruction_queue() {
+        let mut bus = bus::AddressBus::new();
+        let mut biu = BusInterfaceUnit::new(0, 0x1000, 0, 0, 0, vec![], &mut bus);
+        for offset in 0..8 {
+            biu.write_byte(0x10000 + offset, offset as u8 + 1);
+        }
+        biu.fill_instruction_queue();
+        assert_eq!(biu.get_queue_length(), 6);
+        assert_eq!(biu.fetch_instruction_byte(), 1);
+        assert_eq!(biu.get_instruction_pointer(), 1);
+
+        // One byte free is not enough for a word fetch on the 8086.
+        biu.fill_instruction_queue();
+        assert_eq!(biu.get_queue_length(), 5);
+        assert_eq!(biu.fetch_instruction_byte(), 2);
+        biu.fill_instruction_queue();
+        assert_eq!(biu.get_queue_length(), 6);
+        for expected in 3..=8 {
+            assert_eq!(biu.fetch_instruction_byte(), expected);
+        }
+    }
+
+    #[test]
+    fn test_fill_instruction_queue_8088() {
+        let mut bus = bus::AddressBus::new();
+        let mut biu = BusInterfaceUnit::new(0, 0x1000, 0, 0, 0, vec![], &mut bus);
+        biu.set_model(Model::Intel8088);
+        biu.fill_instruction_queue();
+        assert_eq!(biu.get_queue_length(), 4);
+        biu.fetch_instruction_byte();
+        biu.fill_instruction_queue();
+        assert_eq!(biu.get_queue_length(), 4);
+    }
+
+    #[test]
+    fn test_fill_instruction_queue_from_odd_offset() {
+        let mut bus = bus::AddressBus::new();
+        let mut biu = BusInterfaceUnit::new(0, 0x1000, 0, 0, 1, vec![], &mut bus);
+        biu.write_byte(0x10001, 0xAA);
+        biu.write_byte(0x10006, 0xBB);
+        biu.fill_instruction_queue();
+        // A byte at 1, then words at 2, 4 and 6 would overflow; stop at 5 bytes.
+        assert_eq!(biu.get_queue_length(), 5);
+        assert_eq!(biu.fetch_instruction_byte(), 0xAA);
+    }
+
+    #[test]
+    fn test_jumps_flush_instruction_queue() {
+        let mut bus = bus::AddressBus::new();
+        let mut biu = BusInterfaceUnit::new(0, 0x1000, 0, 0, 0, vec![], &mut bus);
+        biu.fill_instruction_queue();
+        biu.set_instruction_pointer(0x0010);
+        assert_eq!(biu.get_queue_length(), 0);
+        biu.fill_instruction_queue();
+        biu.set_segment(SegmentRegister::CS, 0x2000);
+        assert_eq!(biu.get_queue_length(), 0);
+    }
+
+    #[test]
+    fn test_read_and_write_word() {
+        let mut bus = bus::AddressBus::new();
+        let mut biu = BusInterfaceUnit::new(0, 0, 0, 0, 0, vec![], &mut bus);
+        biu.write_word(0x2000, 0x0010, 0x1234);
+        assert_eq!(biu.read_byte(0x20010), 0x34);
+        assert_eq!(biu.read_byte(0x20011), 0x12);
+        assert_eq!(biu.read_word(0x2000, 0x0010), 0x1234);
+    }
+
+    #[test]
+    fn test_word_wraps_within_segment() {
+        let mut bus = bus::AddressBus::new();
+        let mut biu = BusInterfaceUnit::new(0, 0, 0, 0, 0, vec![], &mut bus);
+        biu.write_word(0x2000, 0xFFFF, 0xABCD);
+        assert_eq!(biu.read_byte(0x2FFFF), 0xCD);
+        assert_eq!(biu.read_byte(0x20000), 0xAB);
+    }
+
+    #[test]
+    fn test_odd_word_transfers_are_charged() {
+        let mut bus = bus::AddressBus::new();
+        let mut biu = BusInterfaceUnit::new(0, 0, 0, 0, 0, vec![], &mut bus);
+        biu.read_word(0x2000, 0x0010);
+        assert_eq!(biu.take_bus_clocks(), 0);
+        biu.read_word(0x2000, 0x0011);
+        biu.write_word(0x2000, 0x0013, 0);
+        assert_eq!(biu.take_bus_clocks(), 8);
+        assert_eq!(biu.take_bus_clocks(), 0);
+
+        // The 8088 needs two bus cycles for every word.
+        biu.set_model(Model::Intel8088);
+        biu.read_word(0x2000, 0x0010);
+        assert_eq!(biu.take_bus_clocks(), 4);
+    }
+
+    #[test]
+    fn test_wait_states_are_charged() {
+        struct Slow;
+        impl bus::BusDevice for Slow {
+            fn read(&mut self, _offset: u32) -> u8 {
+                0
+            }
+            fn write(&mut self, _offset: u32, _value: u8) {}
+            fn wait_states(&self) -> u32 {
+                3
+            }
+        }
+
+        let mut bus = bus::AddressBus::new();
+        bus.map_device(0xA0000..=0xAFFFF, Box::new(Slow));
+        let mut biu = BusInterfaceUnit::new(0, 0, 0, 0, 0, vec![], &mut bus);
+        // An aligned word is one bus cycle, so its wait states count once.
+        biu.read_word(0xA000, 0x0000);
+        biu.read_byte(0x10000);
+        assert_eq!(biu.take_bus_clocks(), 3);
+        biu.write_word(0xA000, 0x0002, 0x1234);
+        assert_eq!(biu.take_bus_clocks(), 3);
+
+        // An odd word is two cycles, each with its wait states.
+        biu.read_word(0xA000, 0x0001);
+        assert_eq!(biu.take_bus_clocks(), timing::EXTRA_BUS_CYCLE_CLOCKS + 6);
+
+        // Prefetching fills the 6-byte queue with three word cycles.
+        biu.set_code_segment_address(0xA000);
+        biu.fill_instruction_queue();
+        assert_eq!(biu.take_bus_clocks(), 9);
+
+        // The 8088 needs a cycle per byte.
+        biu.set_model(Model::Intel8088);
+        biu.read_word(0xA000, 0x0000);
+        assert_eq!(biu.take_bus_clocks(), timing::EXTRA_BUS_CYCLE_CLOCKS + 6);
+    }
+
+    #[test]
+    fn test_port_access() {
+        let mut bus = bus::AddressBus::new();
+        let mut biu = BusInterfaceUnit::new(0, 0, 0, 0, 0, vec![], &mut bus);
+        // Nothing is attached, so reads float high and writes go nowhere.
+        biu.write_port_byte(0x60, 0x00);
+        assert_eq!(biu.read_port_byte(0x60), 0xFF);
+        assert_eq!(biu.read_port_word(0x61), 0xFFFF);
+        assert_eq!(biu.take_bus_clocks(), 4);
+        // Memory is a separate address space.
+        assert_eq!(biu.read_byte(0x00060), 0x00);
+    }
+
+    #[test]
+    fn test_addresses_wrap_at_1mb() {
+        let mut bus = bus::AddressBus::new();
+        let biu = BusInterfaceUnit::new(0, 0xFFFF, 0, 0xFFFF, 0x0010, vec![], &mut bus);
+        assert_eq!(biu.get_fetch_address(), 0x00000);
+        assert_eq!(biu.get_data_address(0x0020, None), 0x00010);
+    }
+
+    #[test]
+    fn test_flush_instruction_queue() {
+        let mut bus = bus::AddressBus::new();
+        let mut biu = BusInterfaceUnit::new(0, 0, 0, 0, 0, vec![0x90, 0x90], &mut bus);
+        biu.flush_instruction_queue();
+        assert_eq!(biu.pop_instruction(), None);
+    }
+
+    #[test]
+    fn test_oversized_initial_queue_is_truncated() {
+        let mut bus = bus::AddressBus::new();
+        let mut biu = BusInterfaceUnit::new(0, 0, 0, 0, 0, vec![0x90; 8], &mut bus);
+        biu.fill_instruction_queue();
+        assert_eq!(biu.get_queue_length(), Model::Intel8086.queue_size());
+        for _ in 0..Model::Intel8086.queue_size() {
+            assert_eq!(biu.pop_instruction(), Some(0x90));
+        }
+        assert_eq!(biu.pop_instruction(), None);
+    }
+
     #[test]
     fn test_get_fetch_address() {
         // Given
@@ -1,28 +1,296 @@
-use super::memory::Memory;
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+use super::io::{IoBus, IoDevice};
+use super::memory::{ADDRESS_MASK, Memory};
+
+/// Value read from an address or port that no device answers; the data bus
+/// floats high.
+pub const OPEN_BUS: u8 = 0xFF;
+
+/// A device mapped into the memory address space, such as ROM, video RAM or
+/// memory-mapped registers.
+pub trait BusDevice {
+    /// Returns the byte at `offset` from the start of the device's mapping.
+    fn read(&mut self, offset: u32) -> u8;
+
+    /// Handles a byte written at `offset` from the start of the device's mapping.
+    fn write(&mut self, offset: u32, value: u8);
+
+    /// Returns the number of wait states the device adds to each bus cycle.
+    fn wait_states(&self) -> u32 {
+        0
+    }
+}
+
+/// See the [`IoDevice`] impl for shared devices.
+impl<T: BusDevice> BusDevice for Rc<RefCell<T>> {
+    fn read(&mut self, offset: u32) -> u8 {
+        self.borrow_mut().read(offset)
+    }
+
+    fn write(&mut self, offset: u32, value: u8) {
+        self.borrow_mut().write(offset, value);
+    }
+
+    fn wait_states(&self) -> u32 {
+        self.borrow().wait_states()
+    }
+}
+
+/// A device and the physical address range it answers.
+struct Mapping {
+    range: RangeInclusive<u32>,
+    device: Box<dyn BusDevice>,
+    /// Set to ignore writes, as for ROM.
+    read_only: bool,
+}
 
 #[derive(Default, Debug)]
 pub struct AddressBus {
     address: u32,
+    /// RAM from address 0; answers wherever no device is mapped
     memory: Memory,
+    /// Devices mapped over memory; later mappings take priority
+    devices: Vec<Mapping>,
+    /// Wait states accumulated since they were last taken
+    wait_states: u32,
+    /// The I/O port space, selected instead of memory by the M/IO line
+    io: IoBus,
 }
 
 impl AddressBus {
     pub fn new() -> Self {
+        Self::with_memory(Memory::new())
+    }
+
+    /// Creates a bus whose RAM is `memory`. Addresses beyond the end of the
+    /// memory that no device answers read as [`OPEN_BUS`].
+    pub fn with_memory(memory: Memory) -> Self {
         Self {
             address: 0,
-            memory: Memory::new(),
+            memory,
+            devices: Vec::new(),
+            wait_states: 0,
+            io: IoBus::new(),
         }
     }
 
+    pub fn get_memory(&self) -> &Memory {
+        &self.memory
+    }
+    pub fn get_memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    /// Maps `device` over the given physical address range.
+    pub fn map_device(&mut self, range: RangeInclusive<u32>, device: Box<dyn BusDevice>) {
+        self.devices.push(Mapping {
+            range,
+            device,
+            read_only: false,
+        });
+    }
+
+    /// Maps `device` over the given physical address range, ignoring writes.
+    /// Use this for ROM backed by its own device; images loaded into system
+    /// memory are protected with [`Memory::load_rom`] instead.
+    pub fn map_read_only_device(&mut self, range: RangeInclusive<u32>, device: Box<dyn BusDevice>) {
+        self.devices.push(Mapping {
+            range,
+            device,
+            read_only: true,
+        });
+    }
+
+    /// Returns the wait states of all accesses since the last call, and resets
+    /// the count. Each byte access is counted; the BIU drops the second byte
+    /// of a word that shares a bus cycle with the first.
+    pub fn take_wait_states(&mut self) -> u32 {
+        std::mem::take(&mut self.wait_states)
+    }
+
+    /// Attaches an I/O device to the given port range.
+    pub fn register_io_device(&mut self, ports: RangeInclusive<u16>, device: Box<dyn IoDevice>) {
+        self.io.register(ports, device);
+    }
+
+    pub fn read_io(&mut self, port: u16) -> u8 {
+        self.io.read_byte(port)
+    }
+
+    pub fn write_io(&mut self, port: u16, value: u8) {
+        self.io.write_byte(port, value);
+    }
+
+    pub fn read_io_word(&mut self, port: u16) -> u16 {
+        self.io.read_word(port)
+    }
+
+    pub fn write_io_word(&mut self, port: u16, value: u16) {
+        self.io.write_word(port, value);
+    }
+
     pub fn set_address(&mut self, address: u32) {
-        self.address = address;
+        self.address = address & ADDRESS_MASK;
     }
 
-    pub fn read(&self) -> u8 {
-        self.memory.read(self.address)
+    pub fn read(&mut self) -> u8 {
+        let address = self.address;
+        match self.mapping(address) {
+            Some(mapping) => {
+                let offset = address - mapping.range.start();
+                let value = mapping.device.read(offset);
+                self.wait_states += mapping.device.wait_states();
+                value
+            }
+            None => self.memory.read(address),
+        }
     }
 
     pub fn write(&mut self, value: u8) {
-        self.memory.write(self.address, value);
+        let address = self.address;
+        match self.mapping(address) {
+            Some(mapping) => {
+                if !mapping.read_only {
+                    let offset = address - mapping.range.start();
+                    mapping.device.write(offset, value);
+                }
+                self.wait_states += mapping.device.wait_states();
+            }
+            None => self.memory.write(address, value),
+        }
+    }
+
+    fn mapping(&mut self, address: u32) -> Option<&mut Mapping> {
+        self.devices
+            .iter_mut()
+            .rev()
+            .find(|mapping| mapping.range.contains(&address))
+    }
+}
+
+impl fmt::Debug for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mapping")
+            .field("range", &self.range)
+            .field("read_only", &self.read_only)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A device register block that counts accesses and is slow to answer.
+    #[derive(Default)]
+    struct Registers {
+        values: [u8; 4],
+        reads: usize,
+    }
+
+    impl BusDevice for Registers {
+        fn read(&mut self, offset: u32) -> u8 {
+            self.reads += 1;
+            self.values[offset as usize]
+        }
+
+        fn write(&mut self, offset: u32, value: u8) {
+            self.values[offset as usize] = value;
+        }
+
+        fn wait_states(&self) -> u32 {
+            2
+        }
+    }
+
+    fn read(bus: &mut AddressBus, address: u32) -> u8 {
+        bus.set_address(address);
+        bus.read()
+    }
+
+    fn write(bus: &mut AddressBus, address: u32, value: u8) {
+        bus.set_address(address);
+        bus.write(value);
+    }
+
+    #[test]
+    fn test_memory_answers_by_default() {
+        let mut bus = AddressBus::new();
+        write(&mut bus, 0x12345, 0x42);
+        assert_eq!(read(&mut bus, 0x12345), 0x42);
+        assert_eq!(bus.get_memory().read(0x12345), 0x42);
+        assert_eq!(bus.take_wait_states(), 0);
+    }
+
+    #[test]
+    fn test_unmapped_addresses_are_open_bus() {
+        let mut bus = AddressBus::with_memory(Memory::with_size(0x10000));
+        write(&mut bus, 0x20000, 0x42);
+        assert_eq!(read(&mut bus, 0x20000), OPEN_BUS);
+        assert_eq!(read(&mut bus, 0xFFFFF), OPEN_BUS);
+    }
+
+    #[test]
+    fn test_device_is_addressed_relative_to_its_mapping() {
+        let mut bus = AddressBus::new();
+        let registers = Rc::new(RefCell::new(Registers::default()));
+        bus.map_device(0xC0000..=0xC0003, Box::new(registers.clone()));
+        write(&mut bus, 0xC0002, 0x99);
+        assert_eq!(registers.borrow().values, [0, 0, 0x99, 0]);
+        assert_eq!(read(&mut bus, 0xC0002), 0x99);
+        assert_eq!(registers.borrow().reads, 1);
+        // Memory under the device is hidden.
+        assert_eq!(bus.get_memory().read(0xC0002), 0x00);
+        // Memory next to it is not.
+        write(&mut bus, 0xC0004, 0x11);
+        assert_eq!(bus.get_memory().read(0xC0004), 0x11);
+    }
+
+    #[test]
+    fn test_wait_states_accumulate() {
+        let mut bus = AddressBus::new();
+        bus.map_device(0xC0000..=0xC0003, Box::new(Registers::default()));
+        read(&mut bus, 0xC0000);
+        write(&mut bus, 0xC0001, 0);
+        read(&mut bus, 0x00000);
+        assert_eq!(bus.take_wait_states(), 4);
+        assert_eq!(bus.take_wait_states(), 0);
+    }
+
+    #[test]
+    fn test_read_only_device_ignores_writes() {
+        let mut bus = AddressBus::new();
+        let mut rom = Memory::with_size(0x10);
+        rom.write(0x0, 0xEA);
+        bus.map_read_only_device(0xFFFF0..=0xFFFFF, Box::new(rom));
+        write(&mut bus, 0xFFFF0, 0x00);
+        assert_eq!(read(&mut bus, 0xFFFF0), 0xEA);
+    }
+
+    #[test]
+    fn test_video_ram_as_memory_device() {
+        let mut bus = AddressBus::new();
+        let video = Rc::new(RefCell::new(Memory::with_size(0x8000)));
+        bus.map_device(0xB8000..=0xBFFFF, Box::new(video.clone()));
+        write(&mut bus, 0xB8000, b'A');
+        write(&mut bus, 0xB8001, 0x07);
+        assert_eq!(video.borrow().read_u16(0), 0x0741);
+    }
+
+    #[test]
+    fn test_later_mapping_wins() {
+        let mut bus = AddressBus::new();
+        let low = Rc::new(RefCell::new(Registers::default()));
+        let high = Rc::new(RefCell::new(Registers::default()));
+        bus.map_device(0xD0000..=0xD0003, Box::new(low.clone()));
+        bus.map_device(0xD0002..=0xD0005, Box::new(high.clone()));
+        write(&mut bus, 0xD0001, 1);
+        write(&mut bus, 0xD0002, 2);
+        assert_eq!(low.borrow().values, [0, 1, 0, 0]);
+        assert_eq!(high.borrow().values, [2, 0, 0, 0]);
     }
 }
@@ -0,0 +1,1116 @@
+use std::fmt;
+
+use super::biu::BusInterfaceUnit;
+
+/// A source of instruction bytes for the decoder.
+///
+/// The BIU supplies bytes from its instruction queue, while a byte slice can be
+/// used to decode instructions that are not (yet) in memory.
+pub trait ByteSource {
+    /// Returns the next byte of the instruction stream, or `None` if the stream is exhausted.
+    fn next_byte(&mut self) -> Option<u8>;
+}
+
+impl ByteSource for BusInterfaceUnit<'_> {
+    fn next_byte(&mut self) -> Option<u8> {
+        Some(self.fetch_instruction_byte())
+    }
+}
+
+impl ByteSource for std::slice::Iter<'_, u8> {
+    fn next_byte(&mut self) -> Option<u8> {
+        self.next().copied()
+    }
+}
+
+/// Errors that can occur while decoding an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The byte source ran out in the middle of an instruction.
+    UnexpectedEnd,
+    /// The opcode (or opcode extension in the ModR/M reg field) is not a documented 8086 instruction.
+    InvalidOpcode(u8),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of instruction stream"),
+            DecodeError::InvalidOpcode(opcode) => write!(f, "invalid opcode {opcode:#04x}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// 8-bit general purpose registers, in ModR/M encoding order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register8 {
+    AL,
+    CL,
+    DL,
+    BL,
+    AH,
+    CH,
+    DH,
+    BH,
+}
+
+impl Register8 {
+    /// Returns the register encoded by the 3-bit field `bits`.
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 0b111 {
+            0 => Register8::AL,
+            1 => Register8::CL,
+            2 => Register8::DL,
+            3 => Register8::BL,
+            4 => Register8::AH,
+            5 => Register8::CH,
+            6 => Register8::DH,
+            _ => Register8::BH,
+        }
+    }
+}
+
+/// 16-bit general purpose, pointer and index registers, in ModR/M encoding order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register16 {
+    AX,
+    CX,
+    DX,
+    BX,
+    SP,
+    BP,
+    SI,
+    DI,
+}
+
+impl Register16 {
+    /// Returns the register encoded by the 3-bit field `bits`.
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 0b111 {
+            0 => Register16::AX,
+            1 => Register16::CX,
+            2 => Register16::DX,
+            3 => Register16::BX,
+            4 => Register16::SP,
+            5 => Register16::BP,
+            6 => Register16::SI,
+            _ => Register16::DI,
+        }
+    }
+}
+
+/// Segment registers, in ModR/M encoding order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentRegister {
+    ES,
+    CS,
+    SS,
+    DS,
+}
+
+impl SegmentRegister {
+    /// Returns the segment register encoded by the 2-bit field `bits`.
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => SegmentRegister::ES,
+            1 => SegmentRegister::CS,
+            2 => SegmentRegister::SS,
+            _ => SegmentRegister::DS,
+        }
+    }
+}
+
+/// Width of the data an instruction operates on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandSize {
+    Byte,
+    Word,
+}
+
+/// The register combination used to form an effective address (the ModR/M r/m field).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
+    BxSi,
+    BxDi,
+    BpSi,
+    BpDi,
+    Si,
+    Di,
+    /// `[BP+disp]`; `mod=00 r/m=110` encodes `Direct` instead.
+    Bp,
+    Bx,
+    /// A 16-bit displacement with no base or index register.
+    Direct,
+}
+
+/// Displacement added to the registers of an effective address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Displacement {
+    None,
+    /// An 8-bit displacement, sign-extended to 16 bits.
+    Byte(i8),
+    Word(u16),
+}
+
+impl Displacement {
+    /// Returns the displacement as a 16-bit value to be added to the base and index.
+    pub fn value(&self) -> u16 {
+        match *self {
+            Displacement::None => 0,
+            Displacement::Byte(disp) => disp as i16 as u16,
+            Displacement::Word(disp) => disp,
+        }
+    }
+}
+
+/// A memory operand as encoded by the ModR/M byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryOperand {
+    pub mode: AddressingMode,
+    pub displacement: Displacement,
+}
+
+/// An instruction operand, with ModR/M fields already resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register8(Register8),
+    Register16(Register16),
+    Segment(SegmentRegister),
+    Memory(MemoryOperand),
+    Immediate8(u8),
+    Immediate16(u16),
+    /// An 8-bit immediate that is sign-extended to the operand size (opcode 0x83).
+    SignExtended8(i8),
+    /// A short branch displacement, relative to the next instruction.
+    Relative8(i8),
+    /// A near branch displacement, relative to the next instruction.
+    Relative16(u16),
+    /// A direct far pointer (`segment:offset`).
+    Far {
+        segment: u16,
+        offset: u16,
+    },
+}
+
+/// The repeat prefix of a string instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatPrefix {
+    /// `REP`/`REPE`/`REPZ` (0xF3)
+    Repe,
+    /// `REPNE`/`REPNZ` (0xF2)
+    Repne,
+}
+
+/// Prefixes that preceded an instruction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Prefixes {
+    /// The segment override, if any. The last override wins.
+    pub segment: Option<SegmentRegister>,
+    /// The repeat prefix, if any. The last repeat prefix wins.
+    pub repeat: Option<RepeatPrefix>,
+    /// Set if a `LOCK` prefix was present.
+    pub lock: bool,
+    /// Total number of prefix bytes, including redundant ones.
+    pub count: usize,
+}
+
+/// The condition tested by a conditional jump, in opcode order (0x70 - 0x7F).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Overflow,
+    NotOverflow,
+    Below,
+    NotBelow,
+    Equal,
+    NotEqual,
+    BelowOrEqual,
+    Above,
+    Sign,
+    NotSign,
+    Parity,
+    NotParity,
+    Less,
+    NotLess,
+    LessOrEqual,
+    Greater,
+}
+
+impl Condition {
+    /// Returns the condition encoded by the low nibble of a `Jcc` opcode.
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 0x0F {
+            0x0 => Condition::Overflow,
+            0x1 => Condition::NotOverflow,
+            0x2 => Condition::Below,
+            0x3 => Condition::NotBelow,
+            0x4 => Condition::Equal,
+            0x5 => Condition::NotEqual,
+            0x6 => Condition::BelowOrEqual,
+            0x7 => Condition::Above,
+            0x8 => Condition::Sign,
+            0x9 => Condition::NotSign,
+            0xA => Condition::Parity,
+            0xB => Condition::NotParity,
+            0xC => Condition::Less,
+            0xD => Condition::NotLess,
+            0xE => Condition::LessOrEqual,
+            _ => Condition::Greater,
+        }
+    }
+}
+
+/// Instruction mnemonics of the 8086.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    // Data transfer
+    Mov,
+    Push,
+    Pop,
+    Xchg,
+    In,
+    Out,
+    Xlat,
+    Lea,
+    Lds,
+    Les,
+    Lahf,
+    Sahf,
+    Pushf,
+    Popf,
+    // Arithmetic
+    Add,
+    Adc,
+    Inc,
+    Aaa,
+    Daa,
+    Sub,
+    Sbb,
+    Dec,
+    Neg,
+    Cmp,
+    Aas,
+    Das,
+    Mul,
+    Imul,
+    Aam,
+    Div,
+    Idiv,
+    Aad,
+    Cbw,
+    Cwd,
+    // Logic
+    Not,
+    Shl,
+    Shr,
+    Sar,
+    Rol,
+    Ror,
+    Rcl,
+    Rcr,
+    And,
+    Test,
+    Or,
+    Xor,
+    // String manipulation
+    Movs,
+    Cmps,
+    Scas,
+    Lods,
+    Stos,
+    // Control transfer
+    Call,
+    CallFar,
+    Jmp,
+    JmpFar,
+    Ret,
+    RetFar,
+    Jcc(Condition),
+    Loop,
+    Loope,
+    Loopne,
+    Jcxz,
+    Int,
+    Int3,
+    Into,
+    Iret,
+    // Processor control
+    Clc,
+    Cmc,
+    Stc,
+    Cld,
+    Std,
+    Cli,
+    Sti,
+    Hlt,
+    Wait,
+    Esc,
+}
+
+/// A decoded 8086 instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub prefixes: Prefixes,
+    /// The opcode byte (after any prefixes).
+    pub opcode: u8,
+    pub mnemonic: Mnemonic,
+    /// Width of the data the instruction operates on.
+    pub size: OperandSize,
+    /// The first (destination) operand, if any.
+    pub destination: Option<Operand>,
+    /// The second (source) operand, if any.
+    pub source: Option<Operand>,
+    /// Total length of the instruction in bytes, including prefixes.
+    pub length: usize,
+}
+
+/// Decodes a single instruction from `source`.
+pub fn decode<S: ByteSource + ?Sized>(source: &mut S) -> Result<Instruction, DecodeError> {
+    Decoder { source, length: 0 }.decode()
+}
+
+/// The fields of a ModR/M byte.
+struct ModRM {
+    mode: u8,
+    reg: u8,
+    rm: u8,
+}
+
+struct Decoder<'s, S: ?Sized> {
+    source: &'s mut S,
+    length: usize,
+}
+
+impl<S: ByteSource + ?Sized> Decoder<'_, S> {
+    fn fetch_u8(&mut self) -> Result<u8, DecodeError> {
+        let byte = self.source.next_byte().ok_or(DecodeError::UnexpectedEnd)?;
+        self.length += 1;
+        Ok(byte)
+    }
+
+    fn fetch_u16(&mut self) -> Result<u16, DecodeError> {
+        let low = self.fetch_u8()?;
+        let high = self.fetch_u8()?;
+        Ok(u16::from_le_bytes([low, high]))
+    }
+
+    fn fetch_modrm(&mut self) -> Result<ModRM, DecodeError> {
+        let byte = self.fetch_u8()?;
+        Ok(ModRM {
+            mode: byte >> 6,
+            reg: (byte >> 3) & 0b111,
+            rm: byte & 0b111,
+        })
+    }
+
+    /// Resolves the r/m field of `modrm` into an operand, fetching any displacement.
+    fn rm_operand(&mut self, modrm: &ModRM, size: OperandSize) -> Result<Operand, DecodeError> {
+        if modrm.mode == 0b11 {
+            return Ok(register_operand(modrm.rm, size));
+        }
+
+        let mode = match modrm.rm {
+            0 => AddressingMode::BxSi,
+            1 => AddressingMode::BxDi,
+            2 => AddressingMode::BpSi,
+            3 => AddressingMode::BpDi,
+            4 => AddressingMode::Si,
+            5 => AddressingMode::Di,
+            6 if modrm.mode == 0b00 => AddressingMode::Direct,
+            6 => AddressingMode::Bp,
+            _ => AddressingMode::Bx,
+        };
+        let displacement = match modrm.mode {
+            0b00 if mode == AddressingMode::Direct => Displacement::Word(self.fetch_u16()?),
+            0b00 => Displacement::None,
+            0b01 => Displacement::Byte(self.fetch_u8()? as i8),
+            _ => Displacement::Word(self.fetch_u16()?),
+        };
+        Ok(Operand::Memory(MemoryOperand { mode, displacement }))
+    }
+
+    fn immediate(&mut self, size: OperandSize) -> Result<Operand, DecodeError> {
+        Ok(match size {
+            OperandSize::Byte => Operand::Immediate8(self.fetch_u8()?),
+            OperandSize::Word => Operand::Immediate16(self.fetch_u16()?),
+        })
+    }
+
+    fn decode(&mut self) -> Result<Instruction, DecodeError> {
+        let mut prefixes = Prefixes::default();
+        let opcode = loop {
+            let byte = self.fetch_u8()?;
+            match byte {
+                0x26 | 0x2E | 0x36 | 0x3E => {
+                    prefixes.segment = Some(SegmentRegister::from_bits(byte >> 3));
+                }
+                0xF0 => prefixes.lock = true,
+                0xF2 => prefixes.repeat = Some(RepeatPrefix::Repne),
+                0xF3 => prefixes.repeat = Some(RepeatPrefix::Repe),
+                _ => break byte,
+            }
+            prefixes.count += 1;
+        };
+
+        // Bit 0 of most opcodes selects between byte and word operands.
+        let size = if opcode & 1 == 0 {
+            OperandSize::Byte
+        } else {
+            OperandSize::Word
+        };
+
+        let (mnemonic, size, destination, source) = match opcode {
+            // ALU operations: r/m,reg / reg,r/m / accumulator,immediate
+            0x00..=0x3F if opcode & 0b111 < 6 => {
+                let mnemonic = alu_mnemonic(opcode >> 3);
+                match opcode & 0b111 {
+                    0 | 1 => {
+                        let modrm = self.fetch_modrm()?;
+                        let rm = self.rm_operand(&modrm, size)?;
+                        (
+                            mnemonic,
+                            size,
+                            Some(rm),
+                            Some(register_operand(modrm.reg, size)),
+                        )
+                    }
+                    2 | 3 => {
+                        let modrm = self.fetch_modrm()?;
+                        let rm = self.rm_operand(&modrm, size)?;
+                        (
+                            mnemonic,
+                            size,
+                            Some(register_operand(modrm.reg, size)),
+                            Some(rm),
+                        )
+                    }
+                    _ => {
+                        let immediate = self.immediate(size)?;
+                        (mnemonic, size, Some(accumulator(size)), Some(immediate))
+                    }
+                }
+            }
+            0x06 | 0x0E | 0x16 | 0x1E => (
+                Mnemonic::Push,
+                OperandSize::Word,
+                Some(Operand::Segment(SegmentRegister::from_bits(opcode >> 3))),
+                None,
+            ),
+            0x07 | 0x0F | 0x17 | 0x1F => (
+                Mnemonic::Pop,
+                OperandSize::Word,
+                Some(Operand::Segment(SegmentRegister::from_bits(opcode >> 3))),
+                None,
+            ),
+            0x27 => (Mnemonic::Daa, OperandSize::Byte, None, None),
+            0x2F => (Mnemonic::Das, OperandSize::Byte, None, None),
+            0x37 => (Mnemonic::Aaa, OperandSize::Byte, None, None),
+            0x3F => (Mnemonic::Aas, OperandSize::Byte, None, None),
+            0x40..=0x5F => {
+                let mnemonic = match opcode & 0xF8 {
+                    0x40 => Mnemonic::Inc,
+                    0x48 => Mnemonic::Dec,
+                    0x50 => Mnemonic::Push,
+                    _ => Mnemonic::Pop,
+                };
+                (
+                    mnemonic,
+                    OperandSize::Word,
+                    Some(Operand::Register16(Register16::from_bits(opcode))),
+                    None,
+                )
+            }
+            // The 8086 ignores bit 4 here, so 0x60 - 0x6F alias the conditional jumps.
+            0x60..=0x7F => (
+                Mnemonic::Jcc(Condition::from_bits(opcode)),
+                OperandSize::Byte,
+                Some(Operand::Relative8(self.fetch_u8()? as i8)),
+                None,
+            ),
+            0x80..=0x83 => {
+                let modrm = self.fetch_modrm()?;
+                let rm = self.rm_operand(&modrm, size)?;
+                let immediate = if opcode == 0x83 {
+                    Operand::SignExtended8(self.fetch_u8()? as i8)
+                } else {
+                    self.immediate(size)?
+                };
+                (alu_mnemonic(modrm.reg), size, Some(rm), Some(immediate))
+            }
+            0x84..=0x8B => {
+                let mnemonic = match opcode {
+                    0x84 | 0x85 => Mnemonic::Test,
+                    0x86 | 0x87 => Mnemonic::Xchg,
+                    _ => Mnemonic::Mov,
+                };
+                let modrm = self.fetch_modrm()?;
+                let rm = self.rm_operand(&modrm, size)?;
+                let reg = register_operand(modrm.reg, size);
+                if opcode & 0b10 == 0 {
+                    (mnemonic, size, Some(rm), Some(reg))
+                } else {
+                    (mnemonic, size, Some(reg), Some(rm))
+                }
+            }
+            0x8C | 0x8E => {
+                let modrm = self.fetch_modrm()?;
+                if modrm.reg > 3 {
+                    return Err(DecodeError::InvalidOpcode(opcode));
+                }
+                let rm = self.rm_operand(&modrm, OperandSize::Word)?;
+                let segment = Operand::Segment(SegmentRegister::from_bits(modrm.reg));
+                if opcode == 0x8C {
+                    (Mnemonic::Mov, OperandSize::Word, Some(rm), Some(segment))
+                } else {
+                    (Mnemonic::Mov, OperandSize::Word, Some(segment), Some(rm))
+                }
+            }
+            0x8D | 0xC4 | 0xC5 => {
+                let mnemonic = match opcode {
+                    0x8D => Mnemonic::Lea,
+                    0xC4 => Mnemonic::Les,
+                    _ => Mnemonic::Lds,
+                };
+                let modrm = self.fetch_modrm()?;
+                let rm = self.rm_operand(&modrm, OperandSize::Word)?;
+                (
+                    mnemonic,
+                    OperandSize::Word,
+                    Some(register_operand(modrm.reg, OperandSize::Word)),
+                    Some(rm),
+                )
+            }
+            0x8F => {
+                let modrm = self.fetch_modrm()?;
+                if modrm.reg != 0 {
+                    return Err(DecodeError::InvalidOpcode(opcode));
+                }
+                let rm = self.rm_operand(&modrm, OperandSize::Word)?;
+                (Mnemonic::Pop, OperandSize::Word, Some(rm), None)
+            }
+            0x90..=0x97 => (
+                Mnemonic::Xchg,
+                OperandSize::Word,
+                Some(Operand::Register16(Register16::AX)),
+                Some(Operand::Register16(Register16::from_bits(opcode))),
+            ),
+            0x98 => (Mnemonic::Cbw, OperandSize::Byte, None, None),
+            0x99 => (Mnemonic::Cwd, OperandSize::Word, None, None),
+            0x9A | 0xEA => {
+                let offset = self.fetch_u16()?;
+                let segment = self.fetch_u16()?;
+                let mnemonic = if opcode == 0x9A {
+                    Mnemonic::CallFar
+                } else {
+                    Mnemonic::JmpFar
+                };
+                (
+                    mnemonic,
+                    OperandSize::Word,
+                    Some(Operand::Far { segment, offset }),
+                    None,
+                )
+            }
+            0x9B => (Mnemonic::Wait, OperandSize::Byte, None, None),
+            0x9C => (Mnemonic::Pushf, OperandSize::Word, None, None),
+            0x9D => (Mnemonic::Popf, OperandSize::Word, None, None),
+            0x9E => (Mnemonic::Sahf, OperandSize::Byte, None, None),
+            0x9F => (Mnemonic::Lahf, OperandSize::Byte, None, None),
+            0xA0..=0xA3 => {
+                let memory = Operand::Memory(MemoryOperand {
+                    mode: AddressingMode::Direct,
+                    displacement: Displacement::Word(self.fetch_u16()?),
+                });
+                if opcode & 0b10 == 0 {
+                    (Mnemonic::Mov, size, Some(accumulator(size)), Some(memory))
+                } else {
+                    (Mnemonic::Mov, size, Some(memory), Some(accumulator(size)))
+                }
+            }
+            0xA4..=0xA7 | 0xAA..=0xAF => {
+                let mnemonic = match opcode & 0xFE {
+                    0xA4 => Mnemonic::Movs,
+                    0xA6 => Mnemonic::Cmps,
+                    0xAA => Mnemonic::Stos,
+                    0xAC => Mnemonic::Lods,
+                    _ => Mnemonic::Scas,
+                };
+                (mnemonic, size, None, None)
+            }
+            0xA8 | 0xA9 => {
+                let immediate = self.immediate(size)?;
+                (
+                    Mnemonic::Test,
+                    size,
+                    Some(accumulator(size)),
+                    Some(immediate),
+                )
+            }
+            0xB0..=0xBF => {
+                let size = if opcode & 0b1000 == 0 {
+                    OperandSize::Byte
+                } else {
+                    OperandSize::Word
+                };
+                let immediate = self.immediate(size)?;
+                (
+                    Mnemonic::Mov,
+                    size,
+                    Some(register_operand(opcode, size)),
+                    Some(immediate),
+                )
+            }
+            // The 8086 ignores bit 1 here, so 0xC0/0xC1 and 0xC8/0xC9 alias the returns.
+            0xC0 | 0xC2 | 0xC8 | 0xCA => {
+                let mnemonic = if opcode & 0x08 == 0 {
+                    Mnemonic::Ret
+                } else {
+                    Mnemonic::RetFar
+                };
+                let immediate = Operand::Immediate16(self.fetch_u16()?);
+                (mnemonic, OperandSize::Word, Some(immediate), None)
+            }
+            0xC1 | 0xC3 => (Mnemonic::Ret, OperandSize::Word, None, None),
+            0xC9 | 0xCB => (Mnemonic::RetFar, OperandSize::Word, None, None),
+            0xC6 | 0xC7 => {
+                let modrm = self.fetch_modrm()?;
+                if modrm.reg != 0 {
+                    return Err(DecodeError::InvalidOpcode(opcode));
+                }
+                let rm = self.rm_operand(&modrm, size)?;
+                let immediate = self.immediate(size)?;
+                (Mnemonic::Mov, size, Some(rm), Some(immediate))
+            }
+            0xCC => (Mnemonic::Int3, OperandSize::Byte, None, None),
+            0xCD => (
+                Mnemonic::Int,
+                OperandSize::Byte,
+                Some(Operand::Immediate8(self.fetch_u8()?)),
+                None,
+            ),
+            0xCE => (Mnemonic::Into, OperandSize::Byte, None, None),
+            0xCF => (Mnemonic::Iret, OperandSize::Word, None, None),
+            0xD0..=0xD3 => {
+                let modrm = self.fetch_modrm()?;
+                let mnemonic = match modrm.reg {
+                    0 => Mnemonic::Rol,
+                    1 => Mnemonic::Ror,
+                    2 => Mnemonic::Rcl,
+                    3 => Mnemonic::Rcr,
+                    4 => Mnemonic::Shl,
+                    5 => Mnemonic::Shr,
+                    7 => Mnemonic::Sar,
+                    _ => return Err(DecodeError::InvalidOpcode(opcode)),
+                };
+                let rm = self.rm_operand(&modrm, size)?;
+                let count = if opcode & 0b10 == 0 {
+                    Operand::Immediate8(1)
+                } else {
+                    Operand::Register8(Register8::CL)
+                };
+                (mnemonic, size, Some(rm), Some(count))
+            }
+            0xD4 | 0xD5 => {
+                let mnemonic = if opcode == 0xD4 {
+                    Mnemonic::Aam
+                } else {
+                    Mnemonic::Aad
+                };
+                let base = Operand::Immediate8(self.fetch_u8()?);
+                (mnemonic, OperandSize::Byte, Some(base), None)
+            }
+            0xD7 => (Mnemonic::Xlat, OperandSize::Byte, None, None),
+            0xD8..=0xDF => {
+                let modrm = self.fetch_modrm()?;
+                // The escape opcode is formed from the low opcode bits and the reg field.
+                let escape = ((opcode & 0b111) << 3) | modrm.reg;
+                let rm = self.rm_operand(&modrm, OperandSize::Word)?;
+                (
+                    Mnemonic::Esc,
+                    OperandSize::Word,
+                    Some(Operand::Immediate8(escape)),
+                    Some(rm),
+                )
+            }
+            0xE0..=0xE3 => {
+                let mnemonic = match opcode {
+                    0xE0 => Mnemonic::Loopne,
+                    0xE1 => Mnemonic::Loope,
+                    0xE2 => Mnemonic::Loop,
+                    _ => Mnemonic::Jcxz,
+                };
+                let displacement = Operand::Relative8(self.fetch_u8()? as i8);
+                (mnemonic, OperandSize::Byte, Some(displacement), None)
+            }
+            0xE4 | 0xE5 => {
+                let port = Operand::Immediate8(self.fetch_u8()?);
+                (Mnemonic::In, size, Some(accumulator(size)), Some(port))
+            }
+            0xE6 | 0xE7 => {
+                let port = Operand::Immediate8(self.fetch_u8()?);
+                (Mnemonic::Out, size, Some(port), Some(accumulator(size)))
+            }
+            0xEC | 0xED => (
+                Mnemonic::In,
+                size,
+                Some(accumulator(size)),
+                Some(Operand::Register16(Register16::DX)),
+            ),
+            0xEE | 0xEF => (
+                Mnemonic::Out,
+                size,
+                Some(Operand::Register16(Register16::DX)),
+                Some(accumulator(size)),
+            ),
+            0xE8 | 0xE9 => {
+                let mnemonic = if opcode == 0xE8 {
+                    Mnemonic::Call
+                } else {
+                    Mnemonic::Jmp
+                };
+                let displacement = Operand::Relative16(self.fetch_u16()?);
+                (mnemonic, OperandSize::Word, Some(displacement), None)
+            }
+            0xEB => (
+                Mnemonic::Jmp,
+                OperandSize::Byte,
+                Some(Operand::Relative8(self.fetch_u8()? as i8)),
+                None,
+            ),
+            0xF4 => (Mnemonic::Hlt, OperandSize::Byte, None, None),
+            0xF5 => (Mnemonic::Cmc, OperandSize::Byte, None, None),
+            0xF6 | 0xF7 => {
+                let modrm = self.fetch_modrm()?;
+                let rm = self.rm_operand(&modrm, size)?;
+                match modrm.reg {
+                    0 => {
+                        let immediate = self.immediate(size)?;
+                        (Mnemonic::Test, size, Some(rm), Some(immediate))
+                    }
+                    2 => (Mnemonic::Not, size, Some(rm), None),
+                    3 => (Mnemonic::Neg, size, Some(rm), None),
+                    4 => (Mnemonic::Mul, size, Some(rm), None),
+                    5 => (Mnemonic::Imul, size, Some(rm), None),
+                    6 => (Mnemonic::Div, size, Some(rm), None),
+                    7 => (Mnemonic::Idiv, size, Some(rm), None),
+                    _ => return Err(DecodeError::InvalidOpcode(opcode)),
+                }
+            }
+            0xF8 => (Mnemonic::Clc, OperandSize::Byte, None, None),
+            0xF9 => (Mnemonic::Stc, OperandSize::Byte, None, None),
+            0xFA => (Mnemonic::Cli, OperandSize::Byte, None, None),
+            0xFB => (Mnemonic::Sti, OperandSize::Byte, None, None),
+            0xFC => (Mnemonic::Cld, OperandSize::Byte, None, None),
+            0xFD => (Mnemonic::Std, OperandSize::Byte, None, None),
+            0xFE => {
+                let modrm = self.fetch_modrm()?;
+                let mnemonic = match modrm.reg {
+                    0 => Mnemonic::Inc,
+                    1 => Mnemonic::Dec,
+                    _ => return Err(DecodeError::InvalidOpcode(opcode)),
+                };
+                let rm = self.rm_operand(&modrm, OperandSize::Byte)?;
+                (mnemonic, OperandSize::Byte, Some(rm), None)
+            }
+            0xFF => {
+                let modrm = self.fetch_modrm()?;
+                let mnemonic = match modrm.reg {
+                    0 => Mnemonic::Inc,
+                    1 => Mnemonic::Dec,
+                    2 => Mnemonic::Call,
+                    3 => Mnemonic::CallFar,
+                    4 => Mnemonic::Jmp,
+                    5 => Mnemonic::JmpFar,
+                    6 => Mnemonic::Push,
+                    _ => return Err(DecodeError::InvalidOpcode(opcode)),
+                };
+                let rm = self.rm_operand(&modrm, OperandSize::Word)?;
+                (mnemonic, OperandSize::Word, Some(rm), None)
+            }
+            _ => return Err(DecodeError::InvalidOpcode(opcode)),
+        };
+
+        Ok(Instruction {
+            prefixes,
+            opcode,
+            mnemonic,
+            size,
+            destination,
+            source,
+            length: self.length,
+        })
+    }
+}
+
+/// Returns the ALU operation selected by a 3-bit field (opcode bits 3-5 or the ModR/M reg field).
+fn alu_mnemonic(bits: u8) -> Mnemonic {
+    match bits & 0b111 {
+        0 => Mnemonic::Add,
+        1 => Mnemonic::Or,
+        2 => Mnemonic::Adc,
+        3 => Mnemonic::Sbb,
+        4 => Mnemonic::And,
+        5 => Mnemonic::Sub,
+        6 => Mnemonic::Xor,
+        _ => Mnemonic::Cmp,
+    }
+}
+
+fn register_operand(bits: u8, size: OperandSize) -> Operand {
+    match size {
+        OperandSize::Byte => Operand::Register8(Register8::from_bits(bits)),
+        OperandSize::Word => Operand::Register16(Register16::from_bits(bits)),
+    }
+}
+
+fn accumulator(size: OperandSize) -> Operand {
+    register_operand(0, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::bus;
+    use super::*;
+
+    fn decode_bytes(bytes: &[u8]) -> Result<Instruction, DecodeError> {
+        decode(&mut bytes.iter())
+    }
+
+    #[test]
+    fn test_decode_mov_register_to_register() {
+        // mov bx, cx
+        let instruction = decode_bytes(&[0x89, 0xCB]).unwrap();
+        assert_eq!(instruction.mnemonic, Mnemonic::Mov);
+        assert_eq!(instruction.size, OperandSize::Word);
+        assert_eq!(
+            instruction.destination,
+            Some(Operand::Register16(Register16::BX))
+        );
+        assert_eq!(
+            instruction.source,
+            Some(Operand::Register16(Register16::CX))
+        );
+        assert_eq!(instruction.length, 2);
+    }
+
+    #[test]
+    fn test_decode_memory_operand_with_byte_displacement() {
+        // mov al, [bp+di-2]
+        let instruction = decode_bytes(&[0x8A, 0x43, 0xFE]).unwrap();
+        assert_eq!(
+            instruction.destination,
+            Some(Operand::Register8(Register8::AL))
+        );
+        assert_eq!(
+            instruction.source,
+            Some(Operand::Memory(MemoryOperand {
+                mode: AddressingMode::BpDi,
+                displacement: Displacement::Byte(-2),
+            }))
+        );
+        assert_eq!(instruction.length, 3);
+    }
+
+    #[test]
+    fn test_decode_direct_address() {
+        // mov [0x1234], dx
+        let instruction = decode_bytes(&[0x89, 0x16, 0x34, 0x12]).unwrap();
+        assert_eq!(
+            instruction.destination,
+            Some(Operand::Memory(MemoryOperand {
+                mode: AddressingMode::Direct,
+                displacement: Displacement::Word(0x1234),
+            }))
+        );
+        assert_eq!(instruction.length, 4);
+    }
+
+    #[test]
+    fn test_decode_immediate_to_memory_with_word_displacement() {
+        // mov word [bx+0x1000], 0xBEEF
+        let instruction = decode_bytes(&[0xC7, 0x87, 0x00, 0x10, 0xEF, 0xBE]).unwrap();
+        assert_eq!(
+            instruction.destination,
+            Some(Operand::Memory(MemoryOperand {
+                mode: AddressingMode::Bx,
+                displacement: Displacement::Word(0x1000),
+            }))
+        );
+        assert_eq!(instruction.source, Some(Operand::Immediate16(0xBEEF)));
+        assert_eq!(instruction.length, 6);
+    }
+
+    #[test]
+    fn test_decode_sign_extended_immediate() {
+        // sub sp, -4
+        let instruction = decode_bytes(&[0x83, 0xEC, 0xFC]).unwrap();
+        assert_eq!(instruction.mnemonic, Mnemonic::Sub);
+        assert_eq!(instruction.source, Some(Operand::SignExtended8(-4)));
+    }
+
+    #[test]
+    fn test_decode_prefixes() {
+        // rep es: movsw
+        let instruction = decode_bytes(&[0xF3, 0x26, 0xA5]).unwrap();
+        assert_eq!(instruction.mnemonic, Mnemonic::Movs);
+        assert_eq!(instruction.size, OperandSize::Word);
+        assert_eq!(instruction.prefixes.repeat, Some(RepeatPrefix::Repe));
+        assert_eq!(instruction.prefixes.segment, Some(SegmentRegister::ES));
+        assert_eq!(instruction.prefixes.count, 2);
+        assert_eq!(instruction.length, 3);
+    }
+
+    #[test]
+    fn test_decode_segment_overrides() {
+        for (prefix, segment) in [
+            (0x26, SegmentRegister::ES),
+            (0x2E, SegmentRegister::CS),
+            (0x36, SegmentRegister::SS),
+            (0x3E, SegmentRegister::DS),
+        ] {
+            // mov ax, seg:[bp+2]
+            let instruction = decode_bytes(&[prefix, 0x8B, 0x46, 0x02]).unwrap();
+            assert_eq!(instruction.prefixes.segment, Some(segment));
+            assert_eq!(instruction.length, 4);
+        }
+
+        // A second override replaces the first but still counts towards the length.
+        let instruction = decode_bytes(&[0x26, 0x2E, 0xAC]).unwrap();
+        assert_eq!(instruction.prefixes.segment, Some(SegmentRegister::CS));
+        assert_eq!(instruction.prefixes.count, 2);
+        assert_eq!(instruction.length, 3);
+    }
+
+    #[test]
+    fn test_decode_long_prefix_run() {
+        // The 8086 accepts any number of prefixes.
+        let mut bytes = vec![0x26; 300];
+        bytes.push(0x90);
+        let instruction = decode_bytes(&bytes).unwrap();
+        assert_eq!(instruction.mnemonic, Mnemonic::Xchg);
+        assert_eq!(instruction.prefixes.count, 300);
+        assert_eq!(instruction.length, 301);
+    }
+
+    #[test]
+    fn test_decode_group_opcodes() {
+        // shr word [si], cl
+        let instruction = decode_bytes(&[0xD3, 0x2C]).unwrap();
+        assert_eq!(instruction.mnemonic, Mnemonic::Shr);
+        assert_eq!(instruction.source, Some(Operand::Register8(Register8::CL)));
+
+        // idiv bl
+        let instruction = decode_bytes(&[0xF6, 0xFB]).unwrap();
+        assert_eq!(instruction.mnemonic, Mnemonic::Idiv);
+        assert_eq!(
+            instruction.destination,
+            Some(Operand::Register8(Register8::BL))
+        );
+
+        // jmp far [di]
+        let instruction = decode_bytes(&[0xFF, 0x2D]).unwrap();
+        assert_eq!(instruction.mnemonic, Mnemonic::JmpFar);
+    }
+
+    #[test]
+    fn test_decode_control_transfer() {
+        // jnz -3
+        let instruction = decode_bytes(&[0x75, 0xFD]).unwrap();
+        assert_eq!(instruction.mnemonic, Mnemonic::Jcc(Condition::NotEqual));
+        assert_eq!(instruction.destination, Some(Operand::Relative8(-3)));
+
+        // call 0xF000:0xE05B
+        let instruction = decode_bytes(&[0x9A, 0x5B, 0xE0, 0x00, 0xF0]).unwrap();
+        assert_eq!(instruction.mnemonic, Mnemonic::CallFar);
+        assert_eq!(
+            instruction.destination,
+            Some(Operand::Far {
+                segment: 0xF000,
+                offset: 0xE05B
+            })
+        );
+        assert_eq!(instruction.length, 5);
+    }
+
+    #[test]
+    fn test_decode_every_single_byte_opcode_is_known_or_rejected() {
+        for opcode in 0..=0xFFu8 {
+            let bytes = [opcode, 0, 0, 0, 0, 0];
+            match decode_bytes(&bytes) {
+                Ok(instruction) if instruction.prefixes.count == 0 => {
+                    assert_eq!(instruction.opcode, opcode)
+                }
+                Ok(instruction) => assert_eq!(instruction.opcode, 0),
+                Err(error) => assert!(
+                    matches!(error, DecodeError::InvalidOpcode(_)),
+                    "opcode {opcode:#04x} failed with {error}"
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_opcode_aliases() {
+        let jumps = (0x60..=0x6Fu8).map(|opcode| (opcode, opcode | 0x10));
+        let returns = [(0xC0, 0xC2), (0xC1, 0xC3), (0xC8, 0xCA), (0xC9, 0xCB)];
+        for (opcode, original) in jumps.chain(returns) {
+            let alias = decode_bytes(&[opcode, 0x10, 0x00]).unwrap();
+            let original = decode_bytes(&[original, 0x10, 0x00]).unwrap();
+            assert_eq!(alias.mnemonic, original.mnemonic, "{opcode:#04x}");
+            assert_eq!(alias.destination, original.destination);
+            assert_eq!(alias.length, original.length);
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid_opcode() {
+        assert_eq!(
+            decode_bytes(&[0x8F, 0xC8]),
+            Err(DecodeError::InvalidOpcode(0x8F))
+        );
+        assert_eq!(
+            decode_bytes(&[0xFF, 0xF8]),
+            Err(DecodeError::InvalidOpcode(0xFF))
+        );
+    }
+
+    #[test]
+    fn test_decode_truncated_instruction() {
+        assert_eq!(decode_bytes(&[0xB8, 0x34]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(decode_bytes(&[0xF3]), Err(DecodeError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn test_decode_from_bus_interface_unit() {
+        let mut bus = bus::AddressBus::new();
+        for (offset, byte) in [0xB8, 0x34, 0x12, 0x90].into_iter().enumerate() {
+            bus.set_address(0x1_0000 + offset as u32);
+            bus.write(byte);
+        }
+        let mut biu = BusInterfaceUnit::new(0, 0x1000, 0, 0, 0, vec![], &mut bus);
+
+        let instruction = decode(&mut biu).unwrap();
+        assert_eq!(instruction.source, Some(Operand::Immediate16(0x1234)));
+        assert_eq!(biu.get_instruction_pointer(), 3);
+
+        let instruction = decode(&mut biu).unwrap();
+        assert_eq!(instruction.mnemonic, Mnemonic::Xchg);
+        assert_eq!(biu.get_instruction_pointer(), 4);
+    }
+
+    #[test]
+    fn test_decode_consumes_the_instruction_queue() {
+        let mut bus = bus::AddressBus::new();
+        // Memory holds nop, but the queue already holds inc ax.
+        bus.set_address(0x1_0000);
+        bus.write(0x90);
+        let mut biu = BusInterfaceUnit::new(0, 0x1000, 0, 0, 0, vec![], &mut bus);
+        assert!(biu.push_instruction(0x40));
+
+        let instruction = decode(&mut biu).unwrap();
+        assert_eq!(instruction.mnemonic, Mnemonic::Inc);
+        assert_eq!(biu.get_queue_length(), 0);
+        assert_eq!(biu.get_instruction_pointer(), 1);
+    }
+}
@@ -0,0 +1,623 @@
+//! An 8086 disassembler with Intel and NASM syntax.
+//!
+//! The NASM output matches ndisasm for 8086 code. ndisasm also decodes the
+//! instructions of later CPUs, so these bytes come out differently:
+//!
+//! - ESC (0xD8-0xDF) is shown as `esc` with the 6-bit opcode and the ModRM
+//!   operand, since there is no 8087 to decode for; ndisasm prints the x87
+//!   instruction instead, e.g. `fadd st0` for `D8 C0`.
+//! - 0x0F is `pop cs`, where ndisasm starts a two-byte opcode.
+//! - Opcodes 0x60-0x6F run as aliases of the conditional jumps on the 8086
+//!   and are shown that way, where ndisasm decodes the 80186 instructions.
+//! - 0xC0, 0xC1, 0xC8 and 0xC9 are shown as the returns they alias on the
+//!   8086, where ndisasm decodes the 80186 shifts by an immediate, ENTER and
+//!   LEAVE. Those take a different number of bytes, so the two listings can
+//!   fall out of step after one.
+//! - 0xD6 and 0xF1 are shown as `db`, where ndisasm prints `salc` and
+//!   `int1`. So are ModRM forms with an undefined reg field, such as D0 /6
+//!   and F6 /1, which the 8086 runs as aliases.
+//! - Prefixes are always shown in the order lock, repeat, segment, however
+//!   they were encoded.
+
+use std::fmt;
+
+use super::decode::{
+    self, AddressingMode, Condition, Displacement, Instruction, MemoryOperand, Mnemonic, Operand,
+    OperandSize, Register8, Register16, RepeatPrefix, SegmentRegister,
+};
+
+/// The assembler dialect instructions are rendered in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// Intel/MASM style: `mov word ptr es:[bx+10h], 0FFFFh`
+    #[default]
+    Intel,
+    /// NASM style, as printed by ndisasm: `mov word [es:bx+0x10],0xffff`
+    Nasm,
+}
+
+/// One disassembled instruction, or a byte that could not be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub segment: u16,
+    pub offset: u16,
+    /// The bytes of the instruction, prefixes included
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+/// Bytes ndisasm shows on each line before moving the rest to continuation lines.
+const NDISASM_BYTES_PER_LINE: usize = 8;
+
+impl Line {
+    /// Formats the line exactly as ndisasm does, so that the two listings can
+    /// be diffed: the offset as 8 hex digits, up to eight bytes, the text, and
+    /// any further bytes on continuation lines. The segment is not shown.
+    pub fn to_ndisasm(&self) -> String {
+        let mut chunks = self.bytes.chunks(NDISASM_BYTES_PER_LINE);
+        let first: String = chunks
+            .next()
+            .unwrap_or_default()
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        let mut text = format!("{:08X}  {first:<18}{}", self.offset, self.text);
+        for chunk in chunks {
+            text.push_str("\n         -");
+            for byte in chunk {
+                text.push_str(&format!("{byte:02X}"));
+            }
+        }
+        text
+    }
+}
+
+/// Formats the line in an ndisasm-like layout: address, bytes, text. The
+/// address is shown as `SSSS:OOOO`; see [`Line::to_ndisasm`] for the exact
+/// ndisasm layout.
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: String = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        write!(
+            f,
+            "{:04X}:{:04X}  {bytes:<18}{}",
+            self.segment, self.offset, self.text
+        )
+    }
+}
+
+/// Disassembles `bytes` as code loaded at `cs`:`ip`.
+///
+/// Bytes that do not start a valid instruction, including an instruction cut
+/// off by the end of the slice, are rendered one at a time as `db`. Offsets
+/// wrap around within the segment.
+pub fn disassemble(bytes: &[u8], cs: u16, ip: u16, syntax: Syntax) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut position = 0;
+    while position < bytes.len() {
+        let offset = ip.wrapping_add(position as u16);
+        let (length, text) = match decode::decode(&mut bytes[position..].iter()) {
+            Ok(instruction) => {
+                let next_ip = offset.wrapping_add(instruction.length as u16);
+                let length = instruction.length;
+                (length, format_instruction(&instruction, next_ip, syntax))
+            }
+            Err(_) => (1, format!("db {}", number(bytes[position] as u32, syntax))),
+        };
+        lines.push(Line {
+            segment: cs,
+            offset,
+            bytes: bytes[position..position + length].to_vec(),
+            text,
+        });
+        position += length;
+    }
+    lines
+}
+
+/// Renders a decoded instruction as text. `next_ip` is the offset of the
+/// following instruction, which relative branch targets are computed from.
+pub fn format_instruction(instruction: &Instruction, next_ip: u16, syntax: Syntax) -> String {
+    let prefixes = &instruction.prefixes;
+    // ndisasm prints REP NOP as the PAUSE of later CPUs.
+    if syntax == Syntax::Nasm
+        && instruction.opcode == 0x90
+        && prefixes.repeat == Some(RepeatPrefix::Repe)
+        && prefixes.count == 1
+    {
+        return "pause".to_string();
+    }
+    let mut text = String::new();
+    if prefixes.lock {
+        text.push_str("lock ");
+    }
+    if let Some(repeat) = prefixes.repeat {
+        let compares = matches!(instruction.mnemonic, Mnemonic::Cmps | Mnemonic::Scas);
+        text.push_str(match (repeat, compares) {
+            (RepeatPrefix::Repe, false) => "rep ",
+            (RepeatPrefix::Repe, true) => "repe ",
+            (RepeatPrefix::Repne, _) => "repne ",
+        });
+    }
+    // An override with no memory operand to attach to, as on a string
+    // instruction, is shown as a prefix.
+    let has_memory = [instruction.destination, instruction.source]
+        .iter()
+        .any(|operand| matches!(operand, Some(Operand::Memory(_))));
+    if let Some(segment) = prefixes.segment
+        && !has_memory
+    {
+        text.push_str(segment_name(segment));
+        text.push(' ');
+    }
+    text.push_str(&mnemonic_name(instruction, syntax));
+
+    let separator = match syntax {
+        Syntax::Intel => ", ",
+        Syntax::Nasm => ",",
+    };
+    let operands: Vec<String> = [instruction.destination, instruction.source]
+        .into_iter()
+        .flatten()
+        .map(|operand| format_operand(&operand, instruction, next_ip, syntax))
+        .collect();
+    // NOP is XCHG AX,AX, and AAM/AAD are written without their usual base of 10.
+    let implied = instruction.opcode == 0x90
+        || (matches!(instruction.mnemonic, Mnemonic::Aam | Mnemonic::Aad)
+            && instruction.destination == Some(Operand::Immediate8(10)));
+    if !operands.is_empty() && !implied {
+        text.push(' ');
+        text.push_str(&operands.join(separator));
+    }
+    text
+}
+
+fn mnemonic_name(instruction: &Instruction, syntax: Syntax) -> String {
+    let nasm = syntax == Syntax::Nasm;
+    let sized = |name: &str| match instruction.size {
+        OperandSize::Byte => format!("{name}b"),
+        OperandSize::Word => format!("{name}w"),
+    };
+    let name = match instruction.mnemonic {
+        Mnemonic::Xchg if instruction.opcode == 0x90 => "nop",
+        Mnemonic::Movs => return sized("movs"),
+        Mnemonic::Cmps => return sized("cmps"),
+        Mnemonic::Scas => return sized("scas"),
+        Mnemonic::Lods => return sized("lods"),
+        Mnemonic::Stos => return sized("stos"),
+        Mnemonic::Jcc(condition) => condition_name(condition, syntax),
+        Mnemonic::CallFar => "call",
+        Mnemonic::JmpFar => "jmp",
+        Mnemonic::RetFar => "retf",
+        Mnemonic::Int3 if nasm => "int3",
+        Mnemonic::Int3 => "int 3",
+        Mnemonic::Xlat if nasm => "xlatb",
+        Mnemonic::Loope => "loope",
+        Mnemonic::Loopne => "loopne",
+        mnemonic => return format!("{mnemonic:?}").to_lowercase(),
+    };
+    name.to_string()
+}
+
+/// Returns the name of a conditional jump. NASM uses the forms ndisasm prints.
+fn condition_name(condition: Condition, syntax: Syntax) -> &'static str {
+    let nasm = syntax == Syntax::Nasm;
+    match condition {
+        Condition::Overflow => "jo",
+        Condition::NotOverflow => "jno",
+        Condition::Below if nasm => "jc",
+        Condition::Below => "jb",
+        Condition::NotBelow if nasm => "jnc",
+        Condition::NotBelow => "jae",
+        Condition::Equal if nasm => "jz",
+        Condition::Equal => "je",
+        Condition::NotEqual if nasm => "jnz",
+        Condition::NotEqual => "jne",
+        Condition::BelowOrEqual if nasm => "jna",
+        Condition::BelowOrEqual => "jbe",
+        Condition::Above => "ja",
+        Condition::Sign => "js",
+        Condition::NotSign => "jns",
+        Condition::Parity if nasm => "jpe",
+        Condition::Parity => "jp",
+        Condition::NotParity if nasm => "jpo",
+        Condition::NotParity => "jnp",
+        Condition::Less => "jl",
+        Condition::NotLess if nasm => "jnl",
+        Condition::NotLess => "jge",
+        Condition::LessOrEqual if nasm => "jng",
+        Condition::LessOrEqual => "jle",
+        Condition::Greater => "jg",
+    }
+}
+
+fn format_operand(
+    operand: &Operand,
+    instruction: &Instruction,
+    next_ip: u16,
+    syntax: Syntax,
+) -> String {
+    match *operand {
+        Operand::Register8(register) => register8_name(register).to_string(),
+        Operand::Register16(register) => register16_name(register).to_string(),
+        Operand::Segment(segment) => segment_name(segment).to_string(),
+        Operand::Memory(memory) => format_memory(&memory, instruction, syntax),
+        // The count of a shift by one is part of the opcode.
+        Operand::Immediate8(1) if is_shift(instruction.mnemonic) => "1".to_string(),
+        Operand::Immediate8(value) => number(value as u32, syntax),
+        Operand::Immediate16(value) => number(value as u32, syntax),
+        Operand::SignExtended8(value) => match syntax {
+            Syntax::Intel => number(value as i16 as u16 as u32, syntax),
+            Syntax::Nasm => format!("byte {}", signed(value as i32, syntax)),
+        },
+        Operand::Relative8(displacement) => {
+            let target = number(next_ip.wrapping_add(displacement as u16) as u32, syntax);
+            // Only JMP has a near form to tell the short one apart from.
+            if instruction.mnemonic == Mnemonic::Jmp {
+                format!("short {target}")
+            } else {
+                target
+            }
+        }
+        Operand::Relative16(displacement) => {
+            number(next_ip.wrapping_add(displacement) as u32, syntax)
+        }
+        Operand::Far { segment, offset } => format!(
+            "{}:{}",
+            number(segment as u32, syntax),
+            number(offset as u32, syntax)
+        ),
+    }
+}
+
+/// Formats a memory operand with its segment override and, where the other
+/// operand does not give the size away, a size hint.
+fn format_memory(memory: &MemoryOperand, instruction: &Instruction, syntax: Syntax) -> String {
+    let mut address = match memory.mode {
+        AddressingMode::BxSi => "bx+si",
+        AddressingMode::BxDi => "bx+di",
+        AddressingMode::BpSi => "bp+si",
+        AddressingMode::BpDi => "bp+di",
+        AddressingMode::Si => "si",
+        AddressingMode::Di => "di",
+        AddressingMode::Bp => "bp",
+        AddressingMode::Bx => "bx",
+        AddressingMode::Direct => "",
+    }
+    .to_string();
+    // Byte displacements are signed; word displacements are shown unsigned,
+    // as ndisasm does.
+    match memory.displacement {
+        Displacement::None => {}
+        Displacement::Byte(displacement) => address.push_str(&signed(displacement as i32, syntax)),
+        Displacement::Word(displacement) if memory.mode == AddressingMode::Direct => {
+            address.push_str(&number(displacement as u32, syntax));
+        }
+        Displacement::Word(displacement) => {
+            address.push('+');
+            address.push_str(&number(displacement as u32, syntax));
+        }
+    }
+
+    let segment = instruction
+        .prefixes
+        .segment
+        .map(|segment| format!("{}:", segment_name(segment)))
+        .unwrap_or_default();
+    let far = matches!(instruction.mnemonic, Mnemonic::CallFar | Mnemonic::JmpFar);
+    match syntax {
+        Syntax::Intel => {
+            let hint = if far {
+                "dword ptr "
+            } else if needs_size_hint(instruction) {
+                size_name(instruction.size, syntax)
+            } else {
+                ""
+            };
+            format!("{hint}{segment}[{address}]")
+        }
+        Syntax::Nasm => {
+            let hint = if far {
+                "far "
+            } else if needs_size_hint(instruction) {
+                size_name(instruction.size, syntax)
+            } else {
+                ""
+            };
+            format!("{hint}[{segment}{address}]")
+        }
+    }
+}
+
+/// Returns true if the size of a memory operand is not implied by the other
+/// operand, as with an immediate, a shift count or a single operand.
+fn needs_size_hint(instruction: &Instruction) -> bool {
+    match instruction.mnemonic {
+        Mnemonic::Lea | Mnemonic::Les | Mnemonic::Lds | Mnemonic::Esc => false,
+        Mnemonic::Call | Mnemonic::Jmp => false,
+        mnemonic if is_shift(mnemonic) => true,
+        _ => ![instruction.destination, instruction.source]
+            .iter()
+            .any(|operand| {
+                matches!(
+                    operand,
+                    Some(Operand::Register8(_) | Operand::Register16(_) | Operand::Segment(_))
+                )
+            }),
+    }
+}
+
+fn is_shift(mnemonic: Mnemonic) -> bool {
+    matches!(
+        mnemonic,
+        Mnemonic::Rol
+            | Mnemonic::Ror
+            | Mnemonic::Rcl
+            | Mnemonic::Rcr
+            | Mnemonic::Shl
+            | Mnemonic::Shr
+            | Mnemonic::Sar
+    )
+}
+
+fn size_name(size: OperandSize, syntax: Syntax) -> &'static str {
+    match (size, syntax) {
+        (OperandSize::Byte, Syntax::Intel) => "byte ptr ",
+        (OperandSize::Word, Syntax::Intel) => "word ptr ",
+        (OperandSize::Byte, Syntax::Nasm) => "byte ",
+        (OperandSize::Word, Syntax::Nasm) => "word ",
+    }
+}
+
+/// Formats an unsigned number: `0x1f` for NASM, `1Fh` for Intel, with a
+/// leading zero where the first digit is a letter. Intel numbers below 10
+/// have no suffix.
+fn number(value: u32, syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Nasm => format!("0x{value:x}"),
+        Syntax::Intel if value < 10 => value.to_string(),
+        Syntax::Intel => {
+            let digits = format!("{value:X}h");
+            if digits.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                format!("0{digits}")
+            } else {
+                digits
+            }
+        }
+    }
+}
+
+/// Formats a number with an explicit sign, as a displacement.
+fn signed(value: i32, syntax: Syntax) -> String {
+    let sign = if value < 0 { '-' } else { '+' };
+    format!("{sign}{}", number(value.unsigned_abs(), syntax))
+}
+
+fn register8_name(register: Register8) -> &'static str {
+    match register {
+        Register8::AL => "al",
+        Register8::CL => "cl",
+        Register8::DL => "dl",
+        Register8::BL => "bl",
+        Register8::AH => "ah",
+        Register8::CH => "ch",
+        Register8::DH => "dh",
+        Register8::BH => "bh",
+    }
+}
+
+fn register16_name(register: Register16) -> &'static str {
+    match register {
+        Register16::AX => "ax",
+        Register16::CX => "cx",
+        Register16::DX => "dx",
+        Register16::BX => "bx",
+        Register16::SP => "sp",
+        Register16::BP => "bp",
+        Register16::SI => "si",
+        Register16::DI => "di",
+    }
+}
+
+fn segment_name(segment: SegmentRegister) -> &'static str {
+    match segment {
+        SegmentRegister::ES => "es",
+        SegmentRegister::CS => "cs",
+        SegmentRegister::SS => "ss",
+        SegmentRegister::DS => "ds",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Disassembles a single instruction at 0000:0100.
+    fn text(bytes: &[u8], syntax: Syntax) -> String {
+        let lines = disassemble(bytes, 0x0000, 0x0100, syntax);
+        assert_eq!(lines.len(), 1, "{lines:?}");
+        lines[0].text.clone()
+    }
+
+    #[test]
+    fn test_nasm_matches_ndisasm() {
+        let cases: &[(&[u8], &str)] = &[
+            (&[0xB8, 0x01, 0x00], "mov ax,0x1"),
+            (&[0x89, 0xCB], "mov bx,cx"),
+            (&[0x8A, 0x43, 0xFE], "mov al,[bp+di-0x2]"),
+            (&[0x8B, 0x87, 0x00, 0xF0], "mov ax,[bx+0xf000]"),
+            (&[0x8B, 0x46, 0x00], "mov ax,[bp+0x0]"),
+            (&[0xA1, 0x34, 0x12], "mov ax,[0x1234]"),
+            (&[0xC6, 0x07, 0x01], "mov byte [bx],0x1"),
+            (
+                &[0xC7, 0x06, 0x10, 0x00, 0xFF, 0xFF],
+                "mov word [0x10],0xffff",
+            ),
+            (&[0x26, 0x8B, 0x07], "mov ax,[es:bx]"),
+            (&[0x2E, 0xFF, 0x06, 0x00, 0x01], "inc word [cs:0x100]"),
+            (&[0x83, 0xC4, 0xFE], "add sp,byte -0x2"),
+            (&[0x83, 0x00, 0x05], "add word [bx+si],byte +0x5"),
+            (&[0xD1, 0xE0], "shl ax,1"),
+            (&[0xD2, 0x2F], "shr byte [bx],cl"),
+            (&[0x8E, 0xD8], "mov ds,ax"),
+            (&[0x8C, 0x1F], "mov [bx],ds"),
+            (&[0x8D, 0x40, 0x02], "lea ax,[bx+si+0x2]"),
+            (&[0xC4, 0x1E, 0x00, 0x02], "les bx,[0x200]"),
+            (&[0xFF, 0x36, 0x00, 0x02], "push word [0x200]"),
+            (&[0xFF, 0x17], "call [bx]"),
+            (&[0xFF, 0x1F], "call far [bx]"),
+            (&[0xFF, 0x2F], "jmp far [bx]"),
+            (&[0xEB, 0xFE], "jmp short 0x100"),
+            (&[0xE9, 0x00, 0x10], "jmp 0x1103"),
+            (&[0xE8, 0xFD, 0xFF], "call 0x100"),
+            (&[0x74, 0x02], "jz 0x104"),
+            (&[0x72, 0x02], "jc 0x104"),
+            (&[0x7E, 0x02], "jng 0x104"),
+            (&[0xE2, 0xFE], "loop 0x100"),
+            (&[0xE3, 0x00], "jcxz 0x102"),
+            (&[0xEA, 0x5B, 0xE0, 0x00, 0xF0], "jmp 0xf000:0xe05b"),
+            (&[0x9A, 0x78, 0x56, 0x34, 0x12], "call 0x1234:0x5678"),
+            (&[0xC2, 0x04, 0x00], "ret 0x4"),
+            (&[0xCB], "retf"),
+            (&[0xCD, 0x21], "int 0x21"),
+            (&[0xCC], "int3"),
+            (&[0xE4, 0x60], "in al,0x60"),
+            (&[0xEF], "out dx,ax"),
+            (&[0xE6, 0x20], "out 0x20,al"),
+            (&[0x90], "nop"),
+            (&[0x93], "xchg ax,bx"),
+            (&[0xD7], "xlatb"),
+            (&[0xF3, 0xA4], "rep movsb"),
+            (&[0xF3, 0xA7], "repe cmpsw"),
+            (&[0xF2, 0xAE], "repne scasb"),
+            (&[0x26, 0xAC], "es lodsb"),
+            (&[0xF0, 0x01, 0x07], "lock add [bx],ax"),
+            (&[0xF6, 0x07, 0x80], "test byte [bx],0x80"),
+            (&[0xF7, 0x37], "div word [bx]"),
+            (&[0x1E], "push ds"),
+            (&[0x9C], "pushf"),
+            (&[0xD4, 0x0A], "aam"),
+            (&[0xD5, 0x10], "aad 0x10"),
+            (&[0xF3, 0x90], "pause"),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(text(bytes, Syntax::Nasm), *expected, "{bytes:02X?}");
+        }
+    }
+
+    #[test]
+    fn test_intel_syntax() {
+        let cases: &[(&[u8], &str)] = &[
+            (&[0xB8, 0x01, 0x00], "mov ax, 1"),
+            (&[0xB8, 0xFF, 0xFF], "mov ax, 0FFFFh"),
+            (&[0x8A, 0x43, 0xFE], "mov al, [bp+di-2]"),
+            (&[0x8B, 0x47, 0x10], "mov ax, [bx+10h]"),
+            (&[0xA1, 0x34, 0x12], "mov ax, [1234h]"),
+            (&[0xC6, 0x07, 0x01], "mov byte ptr [bx], 1"),
+            (
+                &[0x26, 0xC7, 0x47, 0x10, 0xFF, 0xFF],
+                "mov word ptr es:[bx+10h], 0FFFFh",
+            ),
+            (&[0x83, 0xC4, 0xFE], "add sp, 0FFFEh"),
+            (&[0xD1, 0x27], "shl word ptr [bx], 1"),
+            (&[0xFF, 0x1F], "call dword ptr [bx]"),
+            (&[0x74, 0x02], "je 104h"),
+            (&[0x73, 0x02], "jae 104h"),
+            (&[0xEB, 0x00], "jmp short 102h"),
+            (&[0xEA, 0x5B, 0xE0, 0x00, 0xF0], "jmp 0F000h:0E05Bh"),
+            (&[0xCC], "int 3"),
+            (&[0xD7], "xlat"),
+            (&[0xF3, 0xAB], "rep stosw"),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(text(bytes, Syntax::Intel), *expected, "{bytes:02X?}");
+        }
+    }
+
+    #[test]
+    fn test_disassemble_sequence() {
+        // mov ax, 0x1234; int 0x21; ret
+        let lines = disassemble(
+            &[0xB8, 0x34, 0x12, 0xCD, 0x21, 0xC3],
+            0x1000,
+            0xFFFE,
+            Syntax::Nasm,
+        );
+        let offsets: Vec<u16> = lines.iter().map(|line| line.offset).collect();
+        assert_eq!(offsets, [0xFFFE, 0x0001, 0x0003]);
+        assert_eq!(lines[1].bytes, [0xCD, 0x21]);
+        assert_eq!(lines[2].text, "ret");
+        assert_eq!(
+            lines[0].to_string(),
+            "1000:FFFE  B83412            mov ax,0x1234"
+        );
+    }
+
+    #[test]
+    fn test_ndisasm_layout() {
+        let lines = disassemble(&[0xB8, 0x34, 0x12], 0x1000, 0x0100, Syntax::Nasm);
+        assert_eq!(
+            lines[0].to_ndisasm(),
+            "00000100  B83412            mov ax,0x1234"
+        );
+
+        // Bytes past the eighth go on continuation lines.
+        let line = Line {
+            segment: 0,
+            offset: 0x0002,
+            bytes: (0x20..0x32).collect(),
+            text: "nop".to_string(),
+        };
+        assert_eq!(
+            line.to_ndisasm(),
+            [
+                "00000002  2021222324252627  nop",
+                "         -28292A2B2C2D2E2F",
+                "         -3031",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_known_ndisasm_differences() {
+        let cases: &[(&[u8], &str)] = &[
+            // ndisasm: fadd st0
+            (&[0xD8, 0xC0], "esc 0x0,ax"),
+            // ndisasm: fld qword [bx]
+            (&[0xDD, 0x07], "esc 0x28,[bx]"),
+            // ndisasm: pusha, one byte long
+            (&[0x60, 0x02], "jo 0x104"),
+            // ndisasm: rol byte [bp+si],0x0
+            (&[0xC0, 0x02, 0x00], "ret 0x2"),
+            // ndisasm: a shift by an immediate, with a ModRM byte
+            (&[0xC1], "ret"),
+            // ndisasm: enter, four bytes long
+            (&[0xC8, 0x01, 0x00], "retf 0x1"),
+            // ndisasm: leave
+            (&[0xC9], "retf"),
+            // ndisasm: salc
+            (&[0xD6], "db 0xd6"),
+            // ndisasm: int1
+            (&[0xF1], "db 0xf1"),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(text(bytes, Syntax::Nasm), *expected, "{bytes:02X?}");
+        }
+        assert_eq!(text(&[0xDD, 0x07], Syntax::Intel), "esc 28h, [bx]");
+        assert_eq!(text(&[0xF3, 0x90], Syntax::Intel), "rep nop");
+    }
+
+    #[test]
+    fn test_undecodable_bytes_become_db() {
+        // An invalid opcode, then an instruction cut off by the end of the slice
+        let lines = disassemble(&[0xF1, 0x90, 0xB8, 0x01], 0, 0, Syntax::Nasm);
+        let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, ["db 0xf1", "nop", "db 0xb8", "db 0x1"]);
+        assert_eq!(text(&[0xF1], Syntax::Intel), "db 0F1h");
+    }
+}
@@ -0,0 +1,205 @@
+use super::biu::BusInterfaceUnit;
+use super::decode::{AddressingMode, Displacement, MemoryOperand, Register16};
+use super::eu::ExecutionUnit;
+
+/// A resolved memory operand: an offset and the segment it is relative to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectiveAddress {
+    /// Offset within the segment
+    pub offset: u16,
+    /// Set for BP-based addressing, which defaults to the stack segment.
+    pub stack_relative: bool,
+    /// Segment override, if the instruction has one.
+    pub alt_base: Option<u16>,
+}
+
+impl EffectiveAddress {
+    /// Computes the effective address of a ModR/M memory operand from the
+    /// current register values. The sum wraps around at 64K.
+    pub fn calculate(memory: &MemoryOperand, eu: &ExecutionUnit, alt_base: Option<u16>) -> Self {
+        let bx = eu.get_register16(Register16::BX);
+        let bp = eu.get_register16(Register16::BP);
+        let si = eu.get_register16(Register16::SI);
+        let di = eu.get_register16(Register16::DI);
+        let base = match memory.mode {
+            AddressingMode::BxSi => bx.wrapping_add(si),
+            AddressingMode::BxDi => bx.wrapping_add(di),
+            AddressingMode::BpSi => bp.wrapping_add(si),
+            AddressingMode::BpDi => bp.wrapping_add(di),
+            AddressingMode::Si => si,
+            AddressingMode::Di => di,
+            AddressingMode::Bp => bp,
+            AddressingMode::Bx => bx,
+            AddressingMode::Direct => 0,
+        };
+        Self {
+            offset: base.wrapping_add(memory.displacement.value()),
+            stack_relative: matches!(
+                memory.mode,
+                AddressingMode::BpSi | AddressingMode::BpDi | AddressingMode::Bp
+            ),
+            alt_base,
+        }
+    }
+
+    /// Returns the segment the address is relative to: the override if there
+    /// is one, otherwise SS for BP-based addressing and DS for the rest.
+    pub fn segment(&self, biu: &BusInterfaceUnit) -> u16 {
+        let default = if self.stack_relative {
+            biu.get_stack_segment_address()
+        } else {
+            biu.get_data_segment_address()
+        };
+        self.alt_base.unwrap_or(default)
+    }
+
+    /// Returns the physical address `delta` bytes past the effective address,
+    /// in SS for BP-based addressing and DS otherwise, unless overridden.
+    /// The offset wraps around within the segment.
+    pub fn physical(&self, biu: &BusInterfaceUnit, delta: u16) -> u32 {
+        let offset = self.offset.wrapping_add(delta);
+        if self.stack_relative {
+            biu.get_bp_address(offset, self.alt_base)
+        } else {
+            biu.get_data_address(offset, self.alt_base)
+        }
+    }
+}
+
+/// Returns the clocks the 8086 takes to calculate the effective address of
+/// `memory`.
+///
+/// A segment override costs two more clocks, which are charged for the prefix.
+pub fn clocks(memory: &MemoryOperand) -> u32 {
+    let displaced = memory.displacement != Displacement::None;
+    match memory.mode {
+        AddressingMode::Direct => 6,
+        AddressingMode::Si | AddressingMode::Di | AddressingMode::Bp | AddressingMode::Bx => {
+            if displaced {
+                9
+            } else {
+                5
+            }
+        }
+        // BP+DI and BX+SI take one clock less than the other two pairs.
+        AddressingMode::BpDi | AddressingMode::BxSi => {
+            if displaced {
+                11
+            } else {
+                7
+            }
+        }
+        AddressingMode::BpSi | AddressingMode::BxDi => {
+            if displaced {
+                12
+            } else {
+                8
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::bus::AddressBus;
+
+    fn operand(mode: AddressingMode, displacement: Displacement) -> MemoryOperand {
+        MemoryOperand { mode, displacement }
+    }
+
+    fn new_eu() -> ExecutionUnit {
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::BX, 0x1000);
+        eu.set_bp(0x2000);
+        eu.set_si(0x0030);
+        eu.set_di(0x0004);
+        eu
+    }
+
+    #[test]
+    fn test_all_addressing_modes() {
+        let eu = new_eu();
+        let cases = [
+            (AddressingMode::BxSi, 0x1030u16, false),
+            (AddressingMode::BxDi, 0x1004, false),
+            (AddressingMode::BpSi, 0x2030, true),
+            (AddressingMode::BpDi, 0x2004, true),
+            (AddressingMode::Si, 0x0030, false),
+            (AddressingMode::Di, 0x0004, false),
+            (AddressingMode::Bp, 0x2000, true),
+            (AddressingMode::Bx, 0x1000, false),
+        ];
+        for (mode, offset, stack_relative) in cases {
+            for (displacement, delta) in [
+                (Displacement::None, 0u16),
+                (Displacement::Byte(-2), 0xFFFE),
+                (Displacement::Word(0x0100), 0x0100),
+            ] {
+                let address = EffectiveAddress::calculate(&operand(mode, displacement), &eu, None);
+                assert_eq!(address.offset, offset.wrapping_add(delta), "{mode:?}");
+                assert_eq!(address.stack_relative, stack_relative, "{mode:?}");
+            }
+        }
+
+        let direct = operand(AddressingMode::Direct, Displacement::Word(0x1234));
+        let address = EffectiveAddress::calculate(&direct, &eu, None);
+        assert_eq!(address.offset, 0x1234);
+        assert!(!address.stack_relative);
+    }
+
+    #[test]
+    fn test_offset_wraps_at_64k() {
+        let mut eu = new_eu();
+        eu.set_register16(Register16::BX, 0xFFFF);
+        let memory = operand(AddressingMode::BxSi, Displacement::Byte(1));
+        assert_eq!(
+            EffectiveAddress::calculate(&memory, &eu, None).offset,
+            0x0030
+        );
+    }
+
+    #[test]
+    fn test_physical_uses_default_segment_or_override() {
+        let mut bus = AddressBus::new();
+        let biu = BusInterfaceUnit::new(0x4000, 0x1000, 0x3000, 0x2000, 0, vec![], &mut bus);
+        let eu = new_eu();
+
+        let memory = operand(AddressingMode::Bx, Displacement::None);
+        let address = EffectiveAddress::calculate(&memory, &eu, None);
+        assert_eq!(address.physical(&biu, 0), 0x21000);
+        assert_eq!(address.physical(&biu, 1), 0x21001);
+
+        let memory = operand(AddressingMode::BpDi, Displacement::None);
+        let address = EffectiveAddress::calculate(&memory, &eu, None);
+        assert_eq!(address.physical(&biu, 0), 0x32004);
+
+        assert_eq!(address.segment(&biu), 0x3000);
+
+        let address = EffectiveAddress::calculate(&memory, &eu, Some(0x4000));
+        assert_eq!(address.physical(&biu, 0), 0x42004);
+        assert_eq!(address.segment(&biu), 0x4000);
+    }
+
+    #[test]
+    fn test_clocks() {
+        let cases = [
+            (AddressingMode::Direct, Displacement::Word(0), 6),
+            (AddressingMode::Si, Displacement::None, 5),
+            (AddressingMode::Bx, Displacement::None, 5),
+            (AddressingMode::Bp, Displacement::Byte(0), 9),
+            (AddressingMode::Di, Displacement::Word(0), 9),
+            (AddressingMode::BpDi, Displacement::None, 7),
+            (AddressingMode::BxSi, Displacement::None, 7),
+            (AddressingMode::BpSi, Displacement::None, 8),
+            (AddressingMode::BxDi, Displacement::None, 8),
+            (AddressingMode::BpDi, Displacement::Byte(1), 11),
+            (AddressingMode::BxSi, Displacement::Word(1), 11),
+            (AddressingMode::BpSi, Displacement::Byte(1), 12),
+            (AddressingMode::BxDi, Displacement::Word(1), 12),
+        ];
+        for (mode, displacement, expected) in cases {
+            assert_eq!(clocks(&operand(mode, displacement)), expected, "{mode:?}");
+        }
+    }
+}
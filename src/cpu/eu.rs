@@ -1,6 +1,24 @@
-use super::{flags, registers};
+use super::biu::BusInterfaceUnit;
+use super::decode::{
+    Condition, Instruction, MemoryOperand, Mnemonic, Operand, OperandSize, Register8, Register16,
+    RepeatPrefix,
+};
+use super::ea::EffectiveAddress;
+use super::{alu, flags, registers, timing};
+
+/// Interrupt raised by DIV, IDIV and AAM when the quotient does not fit.
+pub const DIVIDE_ERROR_VECTOR: u8 = 0;
+/// Interrupt raised after each instruction while the trap flag is set.
+pub const SINGLE_STEP_VECTOR: u8 = 1;
+/// Interrupt raised by the non-maskable interrupt (NMI) pin.
+pub const NMI_VECTOR: u8 = 2;
+/// Interrupt raised by the one-byte `INT 3` instruction.
+pub const BREAKPOINT_VECTOR: u8 = 3;
+/// Interrupt raised by `INTO` when the overflow flag is set.
+pub const OVERFLOW_VECTOR: u8 = 4;
 
 /// Represents the Execution Unit (EU) of the CPU, which is responsible for executing instructions.
+#[derive(Default)]
 pub struct ExecutionUnit {
     /// Accumulator register
     a: registers::Register,
@@ -23,10 +41,21 @@ pub struct ExecutionUnit {
     /// Flags register
     /// The flags register contains various status flags that are set or cleared based on the result of an operation.
     flags: flags::Flags,
+
+    /// Set while a repeated string instruction is part-way through; holds the
+    /// IP that an interrupt arriving between iterations returns to.
+    string_resume_ip: Option<u16>,
+
+    /// Set by HLT; the EU stays idle until an interrupt is serviced.
+    halted: bool,
+
+    /// Clocks taken by the instruction being executed.
+    clocks: u32,
 }
 
 impl ExecutionUnit {
-    fn new(
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
         a: registers::Register,
         b: registers::Register,
         c: registers::Register,
@@ -47,9 +76,21 @@ impl ExecutionUnit {
             si,
             di,
             flags,
+            string_resume_ip: None,
+            halted: false,
+            clocks: 0,
         }
     }
 
+    /// Puts the EU into the 8086 power-on state: all flags cleared, so that
+    /// interrupts are disabled, and the general registers zeroed.
+    ///
+    /// The 8086 leaves the general registers undefined on reset; zeroing them
+    /// keeps runs reproducible.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
     pub fn set_sp(&mut self, value: u16) {
         self.sp = value;
     }
@@ -75,12 +116,1957 @@ impl ExecutionUnit {
     pub fn get_di(&self) -> u16 {
         self.di
     }
+
+    pub fn get_flags(&self) -> &flags::Flags {
+        &self.flags
+    }
+    pub fn get_flags_mut(&mut self) -> &mut flags::Flags {
+        &mut self.flags
+    }
+
+    /// Returns true if the EU has executed HLT and is waiting for an interrupt.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Returns the value of an 8-bit register.
+    pub fn get_register8(&self, register: Register8) -> u8 {
+        match register {
+            Register8::AL => self.a.low(),
+            Register8::CL => self.c.low(),
+            Register8::DL => self.d.low(),
+            Register8::BL => self.b.low(),
+            Register8::AH => self.a.high(),
+            Register8::CH => self.c.high(),
+            Register8::DH => self.d.high(),
+            Register8::BH => self.b.high(),
+        }
+    }
+
+    /// Sets the value of an 8-bit register.
+    pub fn set_register8(&mut self, register: Register8, value: u8) {
+        match register {
+            Register8::AL => self.a.set_low(value),
+            Register8::CL => self.c.set_low(value),
+            Register8::DL => self.d.set_low(value),
+            Register8::BL => self.b.set_low(value),
+            Register8::AH => self.a.set_high(value),
+            Register8::CH => self.c.set_high(value),
+            Register8::DH => self.d.set_high(value),
+            Register8::BH => self.b.set_high(value),
+        }
+    }
+
+    /// Returns the value of a 16-bit register.
+    pub fn get_register16(&self, register: Register16) -> u16 {
+        match register {
+            Register16::AX => self.a.get(),
+            Register16::CX => self.c.get(),
+            Register16::DX => self.d.get(),
+            Register16::BX => self.b.get(),
+            Register16::SP => self.sp,
+            Register16::BP => self.bp,
+            Register16::SI => self.si,
+            Register16::DI => self.di,
+        }
+    }
+
+    /// Sets the value of a 16-bit register.
+    pub fn set_register16(&mut self, register: Register16, value: u16) {
+        match register {
+            Register16::AX => self.a.set(value),
+            Register16::CX => self.c.set(value),
+            Register16::DX => self.d.set(value),
+            Register16::BX => self.b.set(value),
+            Register16::SP => self.sp = value,
+            Register16::BP => self.bp = value,
+            Register16::SI => self.si = value,
+            Register16::DI => self.di = value,
+        }
+    }
+
+    /// Executes a decoded instruction and returns the number of clocks it took.
+    ///
+    /// The BIU is expected to have already advanced IP past the instruction.
+    pub fn execute(&mut self, instruction: &Instruction, biu: &mut BusInterfaceUnit) -> u32 {
+        // A repeated string instruction only pays its start-up time once.
+        let resuming = self.string_resume_ip.take().is_some();
+        self.clocks = timing::base_clocks(instruction);
+        match instruction.mnemonic {
+            Mnemonic::Mov => {
+                let value = self.read_operand(instruction, instruction.source, biu);
+                self.write_operand(instruction, instruction.destination, value, biu);
+            }
+            Mnemonic::Xchg => {
+                let destination = self.read_operand(instruction, instruction.destination, biu);
+                let source = self.read_operand(instruction, instruction.source, biu);
+                self.write_operand(instruction, instruction.destination, source, biu);
+                self.write_operand(instruction, instruction.source, destination, biu);
+            }
+            Mnemonic::Lea => {
+                // LEA with a register source is undefined; the register is left unchanged.
+                if let Some(Operand::Memory(memory)) = instruction.source {
+                    let offset = EffectiveAddress::calculate(&memory, self, None).offset;
+                    self.write_operand(instruction, instruction.destination, offset, biu);
+                }
+            }
+            Mnemonic::Lds | Mnemonic::Les => {
+                if let Some(Operand::Memory(memory)) = instruction.source {
+                    let location = self.memory_location(instruction, &memory, biu);
+                    let offset = self.read_memory(&location, 0, OperandSize::Word, biu);
+                    let segment = self.read_memory(&location, 2, OperandSize::Word, biu);
+                    self.write_operand(instruction, instruction.destination, offset, biu);
+                    if instruction.mnemonic == Mnemonic::Lds {
+                        biu.set_data_segment_address(segment);
+                    } else {
+                        biu.set_extra_segment_address(segment);
+                    }
+                }
+            }
+            Mnemonic::Xlat => {
+                let offset = self.b.get().wrapping_add(self.a.low() as u16);
+                let location = EffectiveAddress {
+                    offset,
+                    stack_relative: false,
+                    alt_base: segment_override(instruction, biu),
+                };
+                let value = self.read_memory(&location, 0, OperandSize::Byte, biu);
+                self.a.set_low(value as u8);
+            }
+            Mnemonic::Lahf => {
+                let value = self.flags.to_word() as u8;
+                self.a.set_high(value);
+            }
+            Mnemonic::Sahf => {
+                let word = (self.flags.to_word() & 0xFF00) | self.a.high() as u16;
+                self.flags = flags::Flags::from_word(word);
+            }
+            Mnemonic::Push => {
+                let value = match instruction.destination {
+                    // The 8086 pushes the value of SP after it has been decremented.
+                    Some(Operand::Register16(Register16::SP)) => self.sp.wrapping_sub(2),
+                    operand => self.read_operand(instruction, operand, biu),
+                };
+                self.push(value, biu);
+            }
+            Mnemonic::Pop => {
+                let value = self.pop(biu);
+                self.write_operand(instruction, instruction.destination, value, biu);
+            }
+            Mnemonic::Pushf => {
+                let value = self.flags.to_word();
+                self.push(value, biu);
+            }
+            Mnemonic::Popf => {
+                let value = self.pop(biu);
+                self.flags = flags::Flags::from_word(value);
+            }
+            Mnemonic::Add | Mnemonic::Adc | Mnemonic::Sub | Mnemonic::Sbb | Mnemonic::Cmp => {
+                let destination = self.read_operand(instruction, instruction.destination, biu);
+                let source = self.read_operand(instruction, instruction.source, biu);
+                let carry = matches!(instruction.mnemonic, Mnemonic::Adc | Mnemonic::Sbb)
+                    && self.flags.get_carry();
+                let size = instruction.size;
+                let result = match instruction.mnemonic {
+                    Mnemonic::Add | Mnemonic::Adc => {
+                        alu::add(&mut self.flags, destination, source, carry, size)
+                    }
+                    _ => alu::sub(&mut self.flags, destination, source, carry, size),
+                };
+                if instruction.mnemonic != Mnemonic::Cmp {
+                    self.write_operand(instruction, instruction.destination, result, biu);
+                }
+            }
+            Mnemonic::Inc | Mnemonic::Dec | Mnemonic::Neg => {
+                let value = self.read_operand(instruction, instruction.destination, biu);
+                let size = instruction.size;
+                let result = match instruction.mnemonic {
+                    Mnemonic::Inc => alu::inc(&mut self.flags, value, size),
+                    Mnemonic::Dec => alu::dec(&mut self.flags, value, size),
+                    _ => alu::neg(&mut self.flags, value, size),
+                };
+                self.write_operand(instruction, instruction.destination, result, biu);
+            }
+            Mnemonic::And | Mnemonic::Or | Mnemonic::Xor | Mnemonic::Test => {
+                let destination = self.read_operand(instruction, instruction.destination, biu);
+                let source = self.read_operand(instruction, instruction.source, biu);
+                let size = instruction.size;
+                let result = match instruction.mnemonic {
+                    Mnemonic::Or => alu::or(&mut self.flags, destination, source, size),
+                    Mnemonic::Xor => alu::xor(&mut self.flags, destination, source, size),
+                    _ => alu::and(&mut self.flags, destination, source, size),
+                };
+                if instruction.mnemonic != Mnemonic::Test {
+                    self.write_operand(instruction, instruction.destination, result, biu);
+                }
+            }
+            Mnemonic::Not => {
+                let value = self.read_operand(instruction, instruction.destination, biu);
+                self.write_operand(instruction, instruction.destination, !value, biu);
+            }
+            Mnemonic::Shl
+            | Mnemonic::Shr
+            | Mnemonic::Sar
+            | Mnemonic::Rol
+            | Mnemonic::Ror
+            | Mnemonic::Rcl
+            | Mnemonic::Rcr => {
+                let value = self.read_operand(instruction, instruction.destination, biu);
+                let count = self.read_operand(instruction, instruction.source, biu) as u8;
+                if instruction.source == Some(Operand::Register8(Register8::CL)) {
+                    self.clocks += timing::SHIFT_CLOCKS_PER_BIT * count as u32;
+                }
+                let result = alu::shift(
+                    &mut self.flags,
+                    instruction.mnemonic,
+                    value,
+                    count,
+                    instruction.size,
+                );
+                self.write_operand(instruction, instruction.destination, result, biu);
+            }
+            Mnemonic::Mul | Mnemonic::Imul => {
+                let multiplier = self.read_operand(instruction, instruction.destination, biu);
+                let size = instruction.size;
+                let multiplicand = self.a.get();
+                let product = if instruction.mnemonic == Mnemonic::Mul {
+                    alu::mul(&mut self.flags, multiplicand, multiplier, size)
+                } else {
+                    alu::imul(&mut self.flags, multiplicand, multiplier, size)
+                };
+                // A byte multiply leaves its product in AX, a word multiply in DX:AX.
+                self.a.set(product as u16);
+                if size == OperandSize::Word {
+                    self.d.set((product >> 16) as u16);
+                }
+            }
+            Mnemonic::Div | Mnemonic::Idiv => {
+                let divisor = self.read_operand(instruction, instruction.destination, biu);
+                let dividend = match instruction.size {
+                    OperandSize::Byte => self.a.get() as u32,
+                    OperandSize::Word => (self.d.get() as u32) << 16 | self.a.get() as u32,
+                };
+                let result = if instruction.mnemonic == Mnemonic::Div {
+                    alu::div(dividend, divisor, instruction.size)
+                } else {
+                    alu::idiv(dividend, divisor, instruction.size)
+                };
+                match (result, instruction.size) {
+                    (Some((quotient, remainder)), OperandSize::Byte) => {
+                        self.a.set_low(quotient as u8);
+                        self.a.set_high(remainder as u8);
+                    }
+                    (Some((quotient, remainder)), OperandSize::Word) => {
+                        self.a.set(quotient);
+                        self.d.set(remainder);
+                    }
+                    // The 8086 pushes the address of the next instruction, not the faulting one.
+                    (None, _) => {
+                        self.clocks += timing::INTERNAL_INTERRUPT_CLOCKS;
+                        self.interrupt(DIVIDE_ERROR_VECTOR, biu);
+                    }
+                }
+            }
+            Mnemonic::Daa => {
+                let result = alu::daa(&mut self.flags, self.a.low());
+                self.a.set_low(result);
+            }
+            Mnemonic::Das => {
+                let result = alu::das(&mut self.flags, self.a.low());
+                self.a.set_low(result);
+            }
+            Mnemonic::Aaa => {
+                let result = alu::aaa(&mut self.flags, self.a.get());
+                self.a.set(result);
+            }
+            Mnemonic::Aas => {
+                let result = alu::aas(&mut self.flags, self.a.get());
+                self.a.set(result);
+            }
+            Mnemonic::Aam => {
+                let base = self.read_operand(instruction, instruction.destination, biu) as u8;
+                match alu::aam(&mut self.flags, self.a.low(), base) {
+                    Some(result) => self.a.set(result),
+                    None => {
+                        self.clocks += timing::INTERNAL_INTERRUPT_CLOCKS;
+                        self.interrupt(DIVIDE_ERROR_VECTOR, biu);
+                    }
+                }
+            }
+            Mnemonic::Aad => {
+                let base = self.read_operand(instruction, instruction.destination, biu) as u8;
+                let result = alu::aad(&mut self.flags, self.a.get(), base);
+                self.a.set(result);
+            }
+            Mnemonic::Movs | Mnemonic::Cmps | Mnemonic::Scas | Mnemonic::Lods | Mnemonic::Stos => {
+                // Later iterations are not fetched again, so neither the
+                // start-up time nor the other prefixes are charged for them.
+                if instruction.prefixes.repeat.is_some() {
+                    if resuming {
+                        self.clocks -= timing::prefix_clocks(instruction);
+                    } else {
+                        self.clocks += timing::REPEAT_STARTUP_CLOCKS;
+                    }
+                }
+                self.execute_string(instruction, biu);
+            }
+            Mnemonic::Jmp => {
+                let target = self.branch_target(instruction, biu);
+                self.jump(target, biu);
+            }
+            Mnemonic::Call => {
+                let target = self.branch_target(instruction, biu);
+                self.push(biu.get_instruction_pointer(), biu);
+                self.jump(target, biu);
+            }
+            Mnemonic::JmpFar | Mnemonic::CallFar => {
+                let Some((segment, offset)) = self.far_target(instruction, biu) else {
+                    return self.clocks;
+                };
+                if instruction.mnemonic == Mnemonic::CallFar {
+                    self.push(biu.get_code_segment_address(), biu);
+                    self.push(biu.get_instruction_pointer(), biu);
+                }
+                self.jump_far(segment, offset, biu);
+            }
+            Mnemonic::Ret | Mnemonic::RetFar => {
+                let offset = self.pop(biu);
+                if instruction.mnemonic == Mnemonic::RetFar {
+                    let segment = self.pop(biu);
+                    self.jump_far(segment, offset, biu);
+                } else {
+                    self.jump(offset, biu);
+                }
+                let release = self.read_operand(instruction, instruction.destination, biu);
+                self.sp = self.sp.wrapping_add(release);
+            }
+            Mnemonic::Jcc(condition) => {
+                if self.condition_met(condition) {
+                    self.clocks += timing::BRANCH_TAKEN_CLOCKS;
+                    let target = self.branch_target(instruction, biu);
+                    self.jump(target, biu);
+                }
+            }
+            Mnemonic::Loop | Mnemonic::Loope | Mnemonic::Loopne => {
+                let count = self.c.get().wrapping_sub(1);
+                self.c.set(count);
+                let taken = count != 0
+                    && match instruction.mnemonic {
+                        Mnemonic::Loope => self.flags.get_zero(),
+                        Mnemonic::Loopne => !self.flags.get_zero(),
+                        _ => true,
+                    };
+                if taken {
+                    self.clocks += match instruction.mnemonic {
+                        Mnemonic::Loopne => timing::LOOPNE_TAKEN_CLOCKS,
+                        _ => timing::BRANCH_TAKEN_CLOCKS,
+                    };
+                    let target = self.branch_target(instruction, biu);
+                    self.jump(target, biu);
+                }
+            }
+            Mnemonic::Jcxz => {
+                if self.c.get() == 0 {
+                    self.clocks += timing::BRANCH_TAKEN_CLOCKS;
+                    let target = self.branch_target(instruction, biu);
+                    self.jump(target, biu);
+                }
+            }
+            Mnemonic::Int => {
+                let vector = self.read_operand(instruction, instruction.destination, biu) as u8;
+                self.interrupt(vector, biu);
+            }
+            Mnemonic::Int3 => self.interrupt(BREAKPOINT_VECTOR, biu),
+            Mnemonic::Into => {
+                if self.flags.get_overflow() {
+                    self.clocks += timing::INTO_TAKEN_CLOCKS;
+                    self.interrupt(OVERFLOW_VECTOR, biu);
+                }
+            }
+            Mnemonic::Iret => {
+                let offset = self.pop(biu);
+                let segment = self.pop(biu);
+                let flags = self.pop(biu);
+                self.jump_far(segment, offset, biu);
+                self.flags = flags::Flags::from_word(flags);
+            }
+            Mnemonic::Cli => self.flags.set_interrupt_enable(false),
+            Mnemonic::Sti => self.flags.set_interrupt_enable(true),
+            Mnemonic::Hlt => self.halted = true,
+            Mnemonic::Clc => self.flags.set_carry(false),
+            Mnemonic::Stc => self.flags.set_carry(true),
+            Mnemonic::Cmc => self.flags.set_carry(!self.flags.get_carry()),
+            Mnemonic::Cld => self.flags.set_direction(false),
+            Mnemonic::Std => self.flags.set_direction(true),
+            Mnemonic::Cbw => {
+                let value = self.a.low() as i8 as i16 as u16;
+                self.a.set(value);
+            }
+            Mnemonic::Cwd => {
+                let value = if self.a.get() & 0x8000 != 0 {
+                    0xFFFF
+                } else {
+                    0
+                };
+                self.d.set(value);
+            }
+            Mnemonic::In => {
+                // The port is an immediate byte or DX.
+                let port = self.read_operand(instruction, instruction.source, biu);
+                let value = match instruction.size {
+                    OperandSize::Byte => biu.read_port_byte(port) as u16,
+                    OperandSize::Word => biu.read_port_word(port),
+                };
+                self.write_operand(instruction, instruction.destination, value, biu);
+            }
+            Mnemonic::Out => {
+                let port = self.read_operand(instruction, instruction.destination, biu);
+                let value = self.read_operand(instruction, instruction.source, biu);
+                match instruction.size {
+                    OperandSize::Byte => biu.write_port_byte(port, value as u8),
+                    OperandSize::Word => biu.write_port_word(port, value),
+                }
+            }
+            // Without a coprocessor attached, the TEST pin is always active.
+            Mnemonic::Wait => {}
+            Mnemonic::Esc => {
+                // The 8086 reads a memory operand so that a coprocessor watching
+                // the bus can capture it; the value itself is discarded.
+                if let Some(Operand::Memory(_)) = instruction.source {
+                    self.read_operand(instruction, instruction.source, biu);
+                }
+            }
+        }
+        self.clocks
+    }
+
+    /// Returns the target offset of a near branch: relative to the next
+    /// instruction for direct forms, or the operand value for indirect forms.
+    fn branch_target(&self, instruction: &Instruction, biu: &mut BusInterfaceUnit) -> u16 {
+        let ip = biu.get_instruction_pointer();
+        match instruction.destination {
+            Some(Operand::Relative8(displacement)) => ip.wrapping_add(displacement as i16 as u16),
+            Some(Operand::Relative16(displacement)) => ip.wrapping_add(displacement),
+            operand => self.read_operand(instruction, operand, biu),
+        }
+    }
+
+    /// Returns the `(segment, offset)` target of a far branch, either direct or
+    /// loaded from a memory doubleword. A register operand has no far pointer to
+    /// load, so `None` is returned and the branch is skipped.
+    fn far_target(
+        &self,
+        instruction: &Instruction,
+        biu: &mut BusInterfaceUnit,
+    ) -> Option<(u16, u16)> {
+        match instruction.destination {
+            Some(Operand::Far { segment, offset }) => Some((segment, offset)),
+            Some(Operand::Memory(memory)) => {
+                let location = self.memory_location(instruction, &memory, biu);
+                let offset = self.read_memory(&location, 0, OperandSize::Word, biu);
+                let segment = self.read_memory(&location, 2, OperandSize::Word, biu);
+                Some((segment, offset))
+            }
+            _ => None,
+        }
+    }
+
+    /// Transfers control to `offset` within the current code segment.
+    fn jump(&mut self, offset: u16, biu: &mut BusInterfaceUnit) {
+        biu.set_instruction_pointer(offset);
+    }
+
+    /// Transfers control to `segment:offset`.
+    fn jump_far(&mut self, segment: u16, offset: u16, biu: &mut BusInterfaceUnit) {
+        biu.set_code_segment_address(segment);
+        self.jump(offset, biu);
+    }
+
+    /// Evaluates the condition of a conditional jump against the current flags.
+    fn condition_met(&self, condition: Condition) -> bool {
+        let flags = &self.flags;
+        let less = flags.get_sign() != flags.get_overflow();
+        match condition {
+            Condition::Overflow => flags.get_overflow(),
+            Condition::NotOverflow => !flags.get_overflow(),
+            Condition::Below => flags.get_carry(),
+            Condition::NotBelow => !flags.get_carry(),
+            Condition::Equal => flags.get_zero(),
+            Condition::NotEqual => !flags.get_zero(),
+            Condition::BelowOrEqual => flags.get_carry() || flags.get_zero(),
+            Condition::Above => !flags.get_carry() && !flags.get_zero(),
+            Condition::Sign => flags.get_sign(),
+            Condition::NotSign => !flags.get_sign(),
+            Condition::Parity => flags.get_parity(),
+            Condition::NotParity => !flags.get_parity(),
+            Condition::Less => less,
+            Condition::NotLess => !less,
+            Condition::LessOrEqual => less || flags.get_zero(),
+            Condition::Greater => !less && !flags.get_zero(),
+        }
+    }
+
+    /// Executes one iteration of a string instruction.
+    ///
+    /// With a repeat prefix, CX is decremented and, unless the repetition has
+    /// finished, IP is moved back to the start of the instruction so that the
+    /// next iteration runs as a separate step and interrupts can be serviced in
+    /// between.
+    fn execute_string(&mut self, instruction: &Instruction, biu: &mut BusInterfaceUnit) {
+        let repeat = instruction.prefixes.repeat;
+        if repeat.is_some() && self.c.get() == 0 {
+            // Only the start-up time and the prefixes are charged.
+            self.clocks -= timing::base_clocks(instruction) - timing::prefix_clocks(instruction);
+            return;
+        }
+
+        let size = instruction.size;
+        let step = match (size, self.flags.get_direction()) {
+            (OperandSize::Byte, false) => 1u16,
+            (OperandSize::Word, false) => 2,
+            (OperandSize::Byte, true) => 1u16.wrapping_neg(),
+            (OperandSize::Word, true) => 2u16.wrapping_neg(),
+        };
+        let alt_base = segment_override(instruction, biu);
+
+        match instruction.mnemonic {
+            Mnemonic::Movs => {
+                let value = self.read_string_source(alt_base, size, biu);
+                self.write_string_destination(value, size, biu);
+                self.si = self.si.wrapping_add(step);
+                self.di = self.di.wrapping_add(step);
+            }
+            Mnemonic::Cmps => {
+                let value = self.read_string_source(alt_base, size, biu);
+                let other = self.read_string_destination(size, biu);
+                alu::sub(&mut self.flags, value, other, false, size);
+                self.si = self.si.wrapping_add(step);
+                self.di = self.di.wrapping_add(step);
+            }
+            Mnemonic::Scas => {
+                let other = self.read_string_destination(size, biu);
+                alu::sub(&mut self.flags, self.a.get(), other, false, size);
+                self.di = self.di.wrapping_add(step);
+            }
+            Mnemonic::Lods => {
+                let value = self.read_string_source(alt_base, size, biu);
+                match size {
+                    OperandSize::Byte => self.a.set_low(value as u8),
+                    OperandSize::Word => self.a.set(value),
+                }
+                self.si = self.si.wrapping_add(step);
+            }
+            Mnemonic::Stos => {
+                self.write_string_destination(self.a.get(), size, biu);
+                self.di = self.di.wrapping_add(step);
+            }
+            _ => unreachable!("{:?} is not a string instruction", instruction.mnemonic),
+        }
+
+        let Some(repeat) = repeat else {
+            return;
+        };
+        let count = self.c.get().wrapping_sub(1);
+        self.c.set(count);
+        // Only CMPS and SCAS look at ZF; REPE and REPNE are plain REP for the rest.
+        let compares = matches!(instruction.mnemonic, Mnemonic::Cmps | Mnemonic::Scas);
+        let finished = count == 0
+            || (compares && repeat == RepeatPrefix::Repe && !self.flags.get_zero())
+            || (compares && repeat == RepeatPrefix::Repne && self.flags.get_zero());
+        if !finished {
+            let start = biu
+                .get_instruction_pointer()
+                .wrapping_sub(instruction.length as u16);
+            biu.set_instruction_pointer(start);
+            // The 8086 only backs up over the prefix immediately preceding the
+            // opcode, so an interrupt loses any earlier prefixes.
+            let opcode = start.wrapping_add(instruction.prefixes.count as u16);
+            self.string_resume_ip = Some(opcode.wrapping_sub(1));
+        }
+    }
+
+    /// Reads the string source operand at DS:SI, or at the override segment.
+    fn read_string_source(
+        &self,
+        alt_base: Option<u16>,
+        size: OperandSize,
+        biu: &mut BusInterfaceUnit,
+    ) -> u16 {
+        match size {
+            OperandSize::Byte => {
+                biu.read_byte(biu.get_string_source_address(self.si, alt_base)) as u16
+            }
+            OperandSize::Word => {
+                let segment = alt_base.unwrap_or(biu.get_data_segment_address());
+                biu.read_word(segment, self.si)
+            }
+        }
+    }
+
+    /// Reads the string destination operand at ES:DI.
+    fn read_string_destination(&self, size: OperandSize, biu: &mut BusInterfaceUnit) -> u16 {
+        match size {
+            OperandSize::Byte => biu.read_byte(biu.get_string_destination_address(self.di)) as u16,
+            OperandSize::Word => biu.read_word(biu.get_extra_segment_address(), self.di),
+        }
+    }
+
+    /// Writes the string destination operand at ES:DI.
+    fn write_string_destination(&self, value: u16, size: OperandSize, biu: &mut BusInterfaceUnit) {
+        match size {
+            OperandSize::Byte => {
+                biu.write_byte(biu.get_string_destination_address(self.di), value as u8);
+            }
+            OperandSize::Word => biu.write_word(biu.get_extra_segment_address(), self.di, value),
+        }
+    }
+
+    /// Enters the interrupt handler for `vector`.
+    ///
+    /// FLAGS, CS and IP are pushed, IF and TF are cleared, and CS:IP is loaded
+    /// from the interrupt vector table at physical address 0. If a repeated
+    /// string instruction was interrupted between iterations, the pushed IP
+    /// points back into it.
+    pub fn interrupt(&mut self, vector: u8, biu: &mut BusInterfaceUnit) {
+        let flags = self.flags.to_word();
+        self.push(flags, biu);
+        self.flags.set_interrupt_enable(false);
+        self.flags.set_trap(false);
+        self.push(biu.get_code_segment_address(), biu);
+        let ip = self
+            .string_resume_ip
+            .take()
+            .unwrap_or(biu.get_instruction_pointer());
+        self.push(ip, biu);
+        self.halted = false;
+
+        let entry = vector as u16 * 4;
+        let ip = biu.read_word(0, entry);
+        let cs = biu.read_word(0, entry + 2);
+        self.jump_far(cs, ip, biu);
+    }
+
+    /// Pushes a word onto the stack at SS:SP.
+    fn push(&mut self, value: u16, biu: &mut BusInterfaceUnit) {
+        self.sp = self.sp.wrapping_sub(2);
+        biu.write_word(biu.get_stack_segment_address(), self.sp, value);
+    }
+
+    /// Pops a word from the stack at SS:SP.
+    fn pop(&mut self, biu: &mut BusInterfaceUnit) -> u16 {
+        let value = biu.read_word(biu.get_stack_segment_address(), self.sp);
+        self.sp = self.sp.wrapping_add(2);
+        value
+    }
+
+    fn memory_location(
+        &self,
+        instruction: &Instruction,
+        memory: &MemoryOperand,
+        biu: &BusInterfaceUnit,
+    ) -> EffectiveAddress {
+        EffectiveAddress::calculate(memory, self, segment_override(instruction, biu))
+    }
+
+    /// Reads a byte or word at `delta` bytes past `location`.
+    fn read_memory(
+        &self,
+        location: &EffectiveAddress,
+        delta: u16,
+        size: OperandSize,
+        biu: &mut BusInterfaceUnit,
+    ) -> u16 {
+        match size {
+            OperandSize::Byte => biu.read_byte(location.physical(biu, delta)) as u16,
+            OperandSize::Word => {
+                let offset = location.offset.wrapping_add(delta);
+                biu.read_word(location.segment(biu), offset)
+            }
+        }
+    }
+
+    /// Writes a byte or word at `delta` bytes past `location`.
+    fn write_memory(
+        &self,
+        location: &EffectiveAddress,
+        delta: u16,
+        size: OperandSize,
+        value: u16,
+        biu: &mut BusInterfaceUnit,
+    ) {
+        match size {
+            OperandSize::Byte => biu.write_byte(location.physical(biu, delta), value as u8),
+            OperandSize::Word => {
+                let offset = location.offset.wrapping_add(delta);
+                biu.write_word(location.segment(biu), offset, value);
+            }
+        }
+    }
+
+    /// Reads the value of an operand, zero-extended to 16 bits.
+    fn read_operand(
+        &self,
+        instruction: &Instruction,
+        operand: Option<Operand>,
+        biu: &mut BusInterfaceUnit,
+    ) -> u16 {
+        match operand {
+            Some(Operand::Register8(register)) => self.get_register8(register) as u16,
+            Some(Operand::Register16(register)) => self.get_register16(register),
+            Some(Operand::Segment(segment)) => biu.get_segment(segment),
+            Some(Operand::Memory(memory)) => {
+                let location = self.memory_location(instruction, &memory, biu);
+                self.read_memory(&location, 0, instruction.size, biu)
+            }
+            Some(Operand::Immediate8(value)) => value as u16,
+            Some(Operand::Immediate16(value)) => value,
+            Some(Operand::SignExtended8(value)) => value as i16 as u16,
+            Some(Operand::Relative8(value)) => value as i16 as u16,
+            Some(Operand::Relative16(value)) => value,
+            Some(Operand::Far { offset, .. }) => offset,
+            None => 0,
+        }
+    }
+
+    /// Writes `value` to an operand, truncating it to the operand size.
+    fn write_operand(
+        &mut self,
+        instruction: &Instruction,
+        operand: Option<Operand>,
+        value: u16,
+        biu: &mut BusInterfaceUnit,
+    ) {
+        match operand {
+            Some(Operand::Register8(register)) => self.set_register8(register, value as u8),
+            Some(Operand::Register16(register)) => self.set_register16(register, value),
+            // Loading CS (POP CS, MOV CS) is a jump; the BIU flushes its queue.
+            Some(Operand::Segment(segment)) => biu.set_segment(segment, value),
+            Some(Operand::Memory(memory)) => {
+                let location = self.memory_location(instruction, &memory, biu);
+                self.write_memory(&location, 0, instruction.size, value, biu);
+            }
+            _ => unreachable!("{operand:?} is not a writable operand"),
+        }
+    }
+}
+
+/// Returns the base of the instruction's segment override, if it has one.
+fn segment_override(instruction: &Instruction, biu: &BusInterfaceUnit) -> Option<u16> {
+    instruction
+        .prefixes
+        .segment
+        .map(|segment| biu.get_segment(segment))
 }
+
 #[cfg(test)]
 mod tests {
+    use super::super::bus::AddressBus;
+    use super::super::decode;
     use super::super::flags::Flags;
+    use super::super::io::IoDevice;
     use super::super::registers::Register;
     use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const CODE_SEGMENT: u16 = 0x1000;
+    const DATA_SEGMENT: u16 = 0x2000;
+    const STACK_SEGMENT: u16 = 0x3000;
+    const EXTRA_SEGMENT: u16 = 0x4000;
+
+    fn new_biu(bus: &mut AddressBus) -> BusInterfaceUnit<'_> {
+        BusInterfaceUnit::new(
+            EXTRA_SEGMENT,
+            CODE_SEGMENT,
+            STACK_SEGMENT,
+            DATA_SEGMENT,
+            0,
+            vec![],
+            bus,
+        )
+    }
+
+    /// Loads `program` at CS:IP and executes `count` instructions from it.
+    fn run(eu: &mut ExecutionUnit, biu: &mut BusInterfaceUnit, program: &[u8], count: usize) {
+        let start = biu.get_fetch_address();
+        for (offset, byte) in program.iter().enumerate() {
+            biu.write_byte(start + offset as u32, *byte);
+        }
+        // Drop bytes prefetched before the program was written.
+        biu.flush_instruction_queue();
+        for _ in 0..count {
+            let instruction = decode::decode(biu).unwrap();
+            eu.execute(&instruction, biu);
+        }
+    }
+
+    fn write_word(biu: &mut BusInterfaceUnit, address: u32, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        biu.write_byte(address, low);
+        biu.write_byte(address + 1, high);
+    }
+
+    fn read_word(biu: &mut BusInterfaceUnit, address: u32) -> u16 {
+        u16::from_le_bytes([biu.read_byte(address), biu.read_byte(address + 1)])
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::AX, 0x1234);
+        eu.set_sp(0x0100);
+        eu.get_flags_mut().set_interrupt_enable(true);
+        eu.get_flags_mut().set_carry(true);
+        eu.halted = true;
+        eu.reset();
+        assert_eq!(eu.get_register16(Register16::AX), 0);
+        assert_eq!(eu.get_sp(), 0);
+        assert_eq!(eu.get_flags().to_word(), 0xF002);
+        assert!(!eu.is_halted());
+    }
+
+    #[test]
+    fn test_register8_aliases_register16() {
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::BX, 0x1234);
+        assert_eq!(eu.get_register8(Register8::BL), 0x34);
+        assert_eq!(eu.get_register8(Register8::BH), 0x12);
+        eu.set_register8(Register8::BH, 0xAB);
+        assert_eq!(eu.get_register16(Register16::BX), 0xAB34);
+    }
+
+    #[test]
+    fn test_mov_immediate_and_register() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        // mov ax, 0x1234; mov cl, 0x56; mov dx, ax; mov ch, al
+        run(
+            &mut eu,
+            &mut biu,
+            &[0xB8, 0x34, 0x12, 0xB1, 0x56, 0x89, 0xC2, 0x88, 0xC5],
+            4,
+        );
+        assert_eq!(eu.get_register16(Register16::AX), 0x1234);
+        assert_eq!(eu.get_register16(Register16::DX), 0x1234);
+        assert_eq!(eu.get_register16(Register16::CX), 0x3456);
+    }
+
+    #[test]
+    fn test_mov_memory_uses_data_segment() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::BX, 0x0100);
+        eu.set_si(0x0020);
+        eu.set_register16(Register16::AX, 0xBEEF);
+        // mov [bx+si+4], ax; mov dl, [bx+si+5]
+        run(&mut eu, &mut biu, &[0x89, 0x40, 0x04, 0x8A, 0x50, 0x05], 2);
+        assert_eq!(read_word(&mut biu, 0x20124), 0xBEEF);
+        assert_eq!(eu.get_register8(Register8::DL), 0xBE);
+    }
+
+    #[test]
+    fn test_mov_memory_bp_uses_stack_segment() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_bp(0x0200);
+        write_word(&mut biu, 0x301FE, 0x4321);
+        // mov ax, [bp-2]
+        run(&mut eu, &mut biu, &[0x8B, 0x46, 0xFE], 1);
+        assert_eq!(eu.get_register16(Register16::AX), 0x4321);
+    }
+
+    #[test]
+    fn test_mov_with_segment_override() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        write_word(&mut biu, 0x40010, 0x5555);
+        // mov ax, es:[0x0010]
+        run(&mut eu, &mut biu, &[0x26, 0xA1, 0x10, 0x00], 1);
+        assert_eq!(eu.get_register16(Register16::AX), 0x5555);
+    }
+
+    #[test]
+    fn test_segment_override_replaces_stack_default_of_bp() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_bp(0x0020);
+        eu.set_si(0x0002);
+        write_word(&mut biu, 0x30022, 0x1111);
+        write_word(&mut biu, 0x20022, 0x2222);
+        write_word(&mut biu, 0x40022, 0x4444);
+        // mov ax, [bp+si]; mov bx, ds:[bp+si]; mov cx, es:[bp]+2
+        run(
+            &mut eu,
+            &mut biu,
+            &[0x8B, 0x02, 0x3E, 0x8B, 0x1A, 0x26, 0x8B, 0x4E, 0x02],
+            3,
+        );
+        assert_eq!(eu.get_register16(Register16::AX), 0x1111);
+        assert_eq!(eu.get_register16(Register16::BX), 0x2222);
+        assert_eq!(eu.get_register16(Register16::CX), 0x4444);
+    }
+
+    #[test]
+    fn test_segment_override_applies_to_every_memory_operand() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_sp(0x0100);
+        eu.set_register16(Register16::BX, 0x0010);
+        write_word(&mut biu, 0x40010, 0x0005);
+        write_word(&mut biu, 0x40012, 0x6000);
+        // ss: push word [bx]  (reads 0x30010)
+        write_word(&mut biu, 0x30010, 0x7777);
+        run(&mut eu, &mut biu, &[0x36, 0xFF, 0x37], 1);
+        assert_eq!(read_word(&mut biu, 0x300FE), 0x7777);
+        // es: add [bx], ax
+        eu.set_register16(Register16::AX, 0x0001);
+        run(&mut eu, &mut biu, &[0x26, 0x01, 0x07], 1);
+        assert_eq!(read_word(&mut biu, 0x40010), 0x0006);
+        // es: lds si, [bx]
+        run(&mut eu, &mut biu, &[0x26, 0xC5, 0x37], 1);
+        assert_eq!(eu.get_si(), 0x0006);
+        assert_eq!(biu.get_data_segment_address(), 0x6000);
+    }
+
+    #[test]
+    fn test_word_operand_wraps_within_segment() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::AX, 0x1234);
+        // mov [0xFFFF], ax
+        run(&mut eu, &mut biu, &[0xA3, 0xFF, 0xFF], 1);
+        assert_eq!(biu.read_byte(0x2FFFF), 0x34);
+        assert_eq!(biu.read_byte(0x20000), 0x12);
+        assert_eq!(biu.read_byte(0x30000), 0x00);
+
+        // push ax with SP = 1 wraps the high byte to SS:0000
+        eu.set_sp(0x0001);
+        run(&mut eu, &mut biu, &[0x50], 1);
+        assert_eq!(eu.get_sp(), 0xFFFF);
+        assert_eq!(biu.read_byte(0x3FFFF), 0x34);
+        assert_eq!(biu.read_byte(0x30000), 0x12);
+    }
+
+    #[test]
+    fn test_mov_immediate_to_memory() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_di(0x0008);
+        // mov byte [di], 0x7F; mov word [0x0030], 0x1122
+        run(
+            &mut eu,
+            &mut biu,
+            &[0xC6, 0x05, 0x7F, 0xC7, 0x06, 0x30, 0x00, 0x22, 0x11],
+            2,
+        );
+        assert_eq!(biu.read_byte(0x20008), 0x7F);
+        assert_eq!(read_word(&mut biu, 0x20030), 0x1122);
+    }
+
+    #[test]
+    fn test_mov_segment_registers() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        // mov ax, 0x5000; mov es, ax; mov bx, ss
+        run(
+            &mut eu,
+            &mut biu,
+            &[0xB8, 0x00, 0x50, 0x8E, 0xC0, 0x8C, 0xD3],
+            3,
+        );
+        assert_eq!(biu.get_extra_segment_address(), 0x5000);
+        assert_eq!(eu.get_register16(Register16::BX), STACK_SEGMENT);
+    }
+
+    #[test]
+    fn test_xchg() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::AX, 0x1111);
+        eu.set_register16(Register16::CX, 0x2222);
+        write_word(&mut biu, 0x20000, 0x3333);
+        // xchg ax, cx; xchg [0x0000], cx; xchg ah, al
+        run(
+            &mut eu,
+            &mut biu,
+            &[0x91, 0x87, 0x0E, 0x00, 0x00, 0x86, 0xC4],
+            3,
+        );
+        assert_eq!(eu.get_register16(Register16::AX), 0x2222);
+        assert_eq!(eu.get_register16(Register16::CX), 0x3333);
+        assert_eq!(read_word(&mut biu, 0x20000), 0x1111);
+    }
+
+    #[test]
+    fn test_lea() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_bp(0xFFF0);
+        eu.set_di(0x0020);
+        // lea si, [bp+di+0x10]
+        run(&mut eu, &mut biu, &[0x8D, 0x73, 0x10], 1);
+        assert_eq!(eu.get_si(), 0x0020);
+    }
+
+    #[test]
+    fn test_lds_and_les() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        write_word(&mut biu, 0x20040, 0xABCD);
+        write_word(&mut biu, 0x20042, 0x6000);
+        // les di, [0x0040]; lds si, [0x0040]
+        run(
+            &mut eu,
+            &mut biu,
+            &[0xC4, 0x3E, 0x40, 0x00, 0xC5, 0x36, 0x40, 0x00],
+            2,
+        );
+        assert_eq!(eu.get_di(), 0xABCD);
+        assert_eq!(biu.get_extra_segment_address(), 0x6000);
+        assert_eq!(eu.get_si(), 0xABCD);
+        assert_eq!(biu.get_data_segment_address(), 0x6000);
+    }
+
+    #[test]
+    fn test_xlat() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        biu.write_byte(0x20105, 0x99);
+        biu.write_byte(0x30105, 0x77);
+        eu.set_register16(Register16::BX, 0x0100);
+        eu.set_register8(Register8::AL, 0x05);
+        // xlat; mov al, 5; ss: xlat
+        run(&mut eu, &mut biu, &[0xD7, 0xB0, 0x05, 0x36, 0xD7], 1);
+        assert_eq!(eu.get_register8(Register8::AL), 0x99);
+        run(&mut eu, &mut biu, &[], 2);
+        assert_eq!(eu.get_register8(Register8::AL), 0x77);
+    }
+
+    #[test]
+    fn test_add_and_adc() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::AX, 0xFFFF);
+        write_word(&mut biu, 0x20010, 0x0001);
+        // add ax, [0x0010]; adc dx, 0x1234
+        run(
+            &mut eu,
+            &mut biu,
+            &[0x03, 0x06, 0x10, 0x00, 0x81, 0xD2, 0x34, 0x12],
+            1,
+        );
+        assert_eq!(eu.get_register16(Register16::AX), 0x0000);
+        assert!(eu.get_flags().get_carry());
+        assert!(eu.get_flags().get_zero());
+        run(&mut eu, &mut biu, &[], 1);
+        assert_eq!(eu.get_register16(Register16::DX), 0x1235);
+        assert!(!eu.get_flags().get_carry());
+    }
+
+    #[test]
+    fn test_sub_sbb_and_cmp() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_register8(Register8::BL, 0x10);
+        biu.write_byte(0x20000, 0x20);
+        // sub bl, 0x11; sbb byte [0x0000], 0x0F; cmp bl, 0xFF
+        run(
+            &mut eu,
+            &mut biu,
+            &[
+                0x80, 0xEB, 0x11, 0x80, 0x1E, 0x00, 0x00, 0x0F, 0x80, 0xFB, 0xFF,
+            ],
+            2,
+        );
+        assert_eq!(eu.get_register8(Register8::BL), 0xFF);
+        assert_eq!(biu.read_byte(0x20000), 0x10);
+        assert!(!eu.get_flags().get_carry());
+        run(&mut eu, &mut biu, &[], 1);
+        assert_eq!(eu.get_register8(Register8::BL), 0xFF);
+        assert!(eu.get_flags().get_zero());
+    }
+
+    #[test]
+    fn test_sign_extended_immediate() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_sp(0x0100);
+        // add sp, -2
+        run(&mut eu, &mut biu, &[0x83, 0xC4, 0xFE], 1);
+        assert_eq!(eu.get_sp(), 0x00FE);
+        assert!(eu.get_flags().get_carry());
+    }
+
+    #[test]
+    fn test_inc_dec_and_neg() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.get_flags_mut().set_carry(true);
+        eu.set_register16(Register16::CX, 0xFFFF);
+        // inc cx; dec byte [0x0000]; neg cx
+        run(
+            &mut eu,
+            &mut biu,
+            &[0x41, 0xFE, 0x0E, 0x00, 0x00, 0xF7, 0xD9],
+            2,
+        );
+        assert_eq!(eu.get_register16(Register16::CX), 0x0000);
+        assert_eq!(biu.read_byte(0x20000), 0xFF);
+        assert!(eu.get_flags().get_carry());
+        run(&mut eu, &mut biu, &[], 1);
+        assert!(!eu.get_flags().get_carry());
+    }
+
+    #[test]
+    fn test_logic_instructions() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::AX, 0x0FF0);
+        eu.get_flags_mut().set_carry(true);
+        // and ax, 0x00FF; or al, 0x01; xor ah, ah; not ax; test al, 0x0E
+        run(
+            &mut eu,
+            &mut biu,
+            &[
+                0x25, 0xFF, 0x00, 0x0C, 0x01, 0x30, 0xE4, 0xF7, 0xD0, 0xA8, 0x0E,
+            ],
+            3,
+        );
+        assert_eq!(eu.get_register16(Register16::AX), 0x00F1);
+        assert!(!eu.get_flags().get_carry());
+        assert!(eu.get_flags().get_zero());
+        run(&mut eu, &mut biu, &[], 2);
+        assert_eq!(eu.get_register16(Register16::AX), 0xFF0E);
+        assert!(!eu.get_flags().get_zero());
+    }
+
+    #[test]
+    fn test_shift_by_one_and_by_cl() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::BX, 0x8001);
+        eu.set_register8(Register8::CL, 4);
+        biu.write_byte(0x20000, 0x81);
+        // shl bx, 1; ror byte [0x0000], cl
+        run(&mut eu, &mut biu, &[0xD1, 0xE3, 0xD2, 0x0E, 0x00, 0x00], 1);
+        assert_eq!(eu.get_register16(Register16::BX), 0x0002);
+        assert!(eu.get_flags().get_carry());
+        assert!(eu.get_flags().get_overflow());
+        run(&mut eu, &mut biu, &[], 1);
+        assert_eq!(biu.read_byte(0x20000), 0x18);
+        assert!(!eu.get_flags().get_carry());
+    }
+
+    #[test]
+    fn test_shift_by_cl_is_not_masked() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::AX, 0xFFFF);
+        eu.set_register8(Register8::CL, 0x20);
+        // shr ax, cl
+        run(&mut eu, &mut biu, &[0xD3, 0xE8], 1);
+        assert_eq!(eu.get_register16(Register16::AX), 0x0000);
+        assert!(eu.get_flags().get_zero());
+    }
+
+    #[test]
+    fn test_mul_and_imul() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::AX, 0x0080);
+        eu.set_register16(Register16::BX, 0x0002);
+        // mul bl; imul bx
+        run(&mut eu, &mut biu, &[0xF6, 0xE3, 0xF7, 0xEB], 1);
+        assert_eq!(eu.get_register16(Register16::AX), 0x0100);
+        assert!(eu.get_flags().get_carry());
+        eu.set_register16(Register16::AX, 0xFFFE);
+        run(&mut eu, &mut biu, &[], 1);
+        assert_eq!(eu.get_register16(Register16::AX), 0xFFFC);
+        assert_eq!(eu.get_register16(Register16::DX), 0xFFFF);
+        assert!(!eu.get_flags().get_carry());
+    }
+
+    #[test]
+    fn test_div_and_idiv() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::AX, 0x0107);
+        eu.set_register8(Register8::CL, 0x10);
+        write_word(&mut biu, 0x20000, 0xFFFD);
+        // div cl; idiv word [0x0000]
+        run(&mut eu, &mut biu, &[0xF6, 0xF1, 0xF7, 0x3E, 0x00, 0x00], 1);
+        assert_eq!(eu.get_register8(Register8::AL), 0x10);
+        assert_eq!(eu.get_register8(Register8::AH), 0x07);
+        // -100 / -3 = 33 remainder -1
+        eu.set_register16(Register16::AX, -100i16 as u16);
+        eu.set_register16(Register16::DX, 0xFFFF);
+        run(&mut eu, &mut biu, &[], 1);
+        assert_eq!(eu.get_register16(Register16::AX), 33);
+        assert_eq!(eu.get_register16(Register16::DX), 0xFFFF);
+    }
+
+    #[test]
+    fn test_divide_error_raises_interrupt_zero() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        // INT 0 handler at 0x5000:0x0123
+        write_word(&mut biu, 0x00000, 0x0123);
+        write_word(&mut biu, 0x00002, 0x5000);
+        eu.set_sp(0x0100);
+        eu.set_register16(Register16::AX, 0x1234);
+        eu.get_flags_mut().set_interrupt_enable(true);
+        eu.get_flags_mut().set_trap(true);
+        eu.get_flags_mut().set_carry(true);
+        // div bl (BL = 0)
+        run(&mut eu, &mut biu, &[0xF6, 0xF3], 1);
+
+        assert_eq!(biu.get_code_segment_address(), 0x5000);
+        assert_eq!(biu.get_instruction_pointer(), 0x0123);
+        assert_eq!(eu.get_register16(Register16::AX), 0x1234);
+        assert!(!eu.get_flags().get_interrupt_enable());
+        assert!(!eu.get_flags().get_trap());
+        assert_eq!(eu.get_sp(), 0x00FA);
+        // IP of the next instruction, CS, FLAGS
+        assert_eq!(read_word(&mut biu, 0x300FA), 0x0002);
+        assert_eq!(read_word(&mut biu, 0x300FC), CODE_SEGMENT);
+        assert_eq!(read_word(&mut biu, 0x300FE) & 0x0301, 0x0301);
+    }
+
+    #[test]
+    fn test_idiv_quotient_overflow_raises_interrupt_zero() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        write_word(&mut biu, 0x00000, 0x0040);
+        write_word(&mut biu, 0x00002, 0x0000);
+        eu.set_sp(0x0100);
+        // -256 / 2 = -128, which the 8086 cannot represent
+        eu.set_register16(Register16::AX, 0xFF00);
+        eu.set_register8(Register8::BL, 0x02);
+        // idiv bl
+        run(&mut eu, &mut biu, &[0xF6, 0xFB], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0x0040);
+        assert_eq!(eu.get_register16(Register16::AX), 0xFF00);
+    }
+
+    #[test]
+    fn test_bcd_addition_and_subtraction() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        // mov al, 0x38; add al, 0x45; daa; sub al, 0x99; das
+        run(
+            &mut eu,
+            &mut biu,
+            &[0xB0, 0x38, 0x04, 0x45, 0x27, 0x2C, 0x99, 0x2F],
+            3,
+        );
+        assert_eq!(eu.get_register8(Register8::AL), 0x83);
+        run(&mut eu, &mut biu, &[], 2);
+        assert_eq!(eu.get_register8(Register8::AL), 0x84);
+        assert!(eu.get_flags().get_carry());
+    }
+
+    #[test]
+    fn test_ascii_adjust() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        // mov ax, 0x0009; add al, 0x08; aaa
+        run(&mut eu, &mut biu, &[0xB8, 0x09, 0x00, 0x04, 0x08, 0x37], 3);
+        assert_eq!(eu.get_register16(Register16::AX), 0x0107);
+        // aad; aam 16
+        run(&mut eu, &mut biu, &[0xD5, 0x0A, 0xD4, 0x10], 1);
+        assert_eq!(eu.get_register16(Register16::AX), 0x0011);
+        run(&mut eu, &mut biu, &[], 1);
+        assert_eq!(eu.get_register16(Register16::AX), 0x0101);
+    }
+
+    #[test]
+    fn test_aam_zero_raises_interrupt_zero() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        write_word(&mut biu, 0x00000, 0x0200);
+        eu.set_sp(0x0100);
+        eu.set_register16(Register16::AX, 0x0042);
+        // aam 0
+        run(&mut eu, &mut biu, &[0xD4, 0x00], 1);
+        assert_eq!(biu.get_code_segment_address(), 0x0000);
+        assert_eq!(biu.get_instruction_pointer(), 0x0200);
+        assert_eq!(eu.get_register16(Register16::AX), 0x0042);
+        assert_eq!(read_word(&mut biu, 0x300FA), 0x0002);
+    }
+
+    /// Loads `program` at CS:IP and executes it until IP reaches `end`.
+    fn run_to(eu: &mut ExecutionUnit, biu: &mut BusInterfaceUnit, program: &[u8], end: u16) {
+        run(eu, biu, program, 0);
+        while biu.get_instruction_pointer() != end {
+            run(eu, biu, &[], 1);
+        }
+    }
+
+    #[test]
+    fn test_movs_steps_with_direction_flag() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        write_word(&mut biu, 0x20010, 0xCAFE);
+        eu.set_si(0x0010);
+        eu.set_di(0x0020);
+        // movsw; std; movsb
+        run(&mut eu, &mut biu, &[0xA5, 0xFD, 0xA4], 1);
+        assert_eq!(read_word(&mut biu, 0x40020), 0xCAFE);
+        assert_eq!(eu.get_si(), 0x0012);
+        assert_eq!(eu.get_di(), 0x0022);
+        run(&mut eu, &mut biu, &[], 2);
+        assert!(eu.get_flags().get_direction());
+        assert_eq!(eu.get_si(), 0x0011);
+        assert_eq!(eu.get_di(), 0x0021);
+    }
+
+    #[test]
+    fn test_rep_movsb_copies_cx_bytes() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        for offset in 0..8 {
+            biu.write_byte(0x20100 + offset, offset as u8 + 1);
+        }
+        eu.set_si(0x0100);
+        eu.set_di(0x0200);
+        eu.set_register16(Register16::CX, 5);
+        // rep movsb
+        run_to(&mut eu, &mut biu, &[0xF3, 0xA4], 2);
+        for offset in 0..5 {
+            assert_eq!(biu.read_byte(0x40200 + offset), offset as u8 + 1);
+        }
+        assert_eq!(biu.read_byte(0x40205), 0);
+        assert_eq!(eu.get_register16(Register16::CX), 0);
+        assert_eq!(eu.get_si(), 0x0105);
+        assert_eq!(eu.get_di(), 0x0205);
+    }
+
+    #[test]
+    fn test_rep_with_zero_count_does_nothing() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::AX, 0xFFFF);
+        // rep stosw
+        run(&mut eu, &mut biu, &[0xF3, 0xAB], 1);
+        assert_eq!(biu.get_instruction_pointer(), 2);
+        assert_eq!(eu.get_di(), 0);
+        assert_eq!(read_word(&mut biu, 0x40000), 0);
+    }
+
+    #[test]
+    fn test_rep_stosw_backwards() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.get_flags_mut().set_direction(true);
+        eu.set_register16(Register16::AX, 0x1234);
+        eu.set_register16(Register16::CX, 3);
+        eu.set_di(0x0004);
+        // rep stosw
+        run_to(&mut eu, &mut biu, &[0xF3, 0xAB], 2);
+        assert_eq!(read_word(&mut biu, 0x40000), 0x1234);
+        assert_eq!(read_word(&mut biu, 0x40002), 0x1234);
+        assert_eq!(read_word(&mut biu, 0x40004), 0x1234);
+        assert_eq!(eu.get_di(), 0xFFFE);
+    }
+
+    #[test]
+    fn test_repe_cmpsb_stops_at_mismatch() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        for (offset, (a, b)) in b"HELLO".iter().zip(b"HELP!").enumerate() {
+            biu.write_byte(0x20000 + offset as u32, *a);
+            biu.write_byte(0x40000 + offset as u32, *b);
+        }
+        eu.set_register16(Register16::CX, 5);
+        // repe cmpsb
+        run_to(&mut eu, &mut biu, &[0xF3, 0xA6], 2);
+        assert!(!eu.get_flags().get_zero());
+        assert_eq!(eu.get_register16(Register16::CX), 1);
+        assert_eq!(eu.get_si(), 4);
+    }
+
+    #[test]
+    fn test_repne_scasb_finds_byte() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        for (offset, byte) in b"abc$def".iter().enumerate() {
+            biu.write_byte(0x40000 + offset as u32, *byte);
+        }
+        eu.set_register8(Register8::AL, b'$');
+        eu.set_register16(Register16::CX, 0xFFFF);
+        // repne scasb
+        run_to(&mut eu, &mut biu, &[0xF2, 0xAE], 2);
+        assert!(eu.get_flags().get_zero());
+        assert_eq!(eu.get_di(), 4);
+        assert_eq!(eu.get_register16(Register16::CX), 0xFFFB);
+    }
+
+    #[test]
+    fn test_lods_with_source_segment_override() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        write_word(&mut biu, 0x30008, 0x7788);
+        eu.set_si(0x0008);
+        // ss: lodsw
+        run(&mut eu, &mut biu, &[0x36, 0xAD], 1);
+        assert_eq!(eu.get_register16(Register16::AX), 0x7788);
+        assert_eq!(eu.get_si(), 0x000A);
+    }
+
+    #[test]
+    fn test_movs_override_applies_only_to_source() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        biu.write_byte(0x10040, 0x5A);
+        eu.set_si(0x0040);
+        eu.set_di(0x0050);
+        // cs: movsb
+        run(&mut eu, &mut biu, &[0x2E, 0xA4], 1);
+        assert_eq!(biu.read_byte(0x40050), 0x5A);
+        assert_eq!(biu.read_byte(0x10050), 0x00);
+    }
+
+    #[test]
+    fn test_interrupted_rep_resumes_remaining_iterations() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        write_word(&mut biu, 0x00080, 0x0000);
+        write_word(&mut biu, 0x00082, 0x0500);
+        eu.set_sp(0x0100);
+        eu.set_register16(Register16::CX, 4);
+        // nop; rep stosb
+        run(&mut eu, &mut biu, &[0x90, 0xF3, 0xAA], 3);
+        assert_eq!(eu.get_register16(Register16::CX), 2);
+        assert_eq!(biu.get_instruction_pointer(), 1);
+
+        eu.interrupt(0x20, &mut biu);
+        assert_eq!(biu.get_code_segment_address(), 0x0500);
+        assert_eq!(read_word(&mut biu, 0x300FA), 0x0001);
+    }
+
+    #[test]
+    fn test_interrupted_rep_loses_extra_prefixes() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_sp(0x0100);
+        eu.set_register16(Register16::CX, 4);
+        // es: rep movsb
+        run(&mut eu, &mut biu, &[0x26, 0xF3, 0xA4], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0);
+
+        // The return address skips the ES override and points at REP.
+        eu.interrupt(0x20, &mut biu);
+        assert_eq!(read_word(&mut biu, 0x300FA), 0x0001);
+    }
+
+    #[test]
+    fn test_completed_rep_does_not_affect_interrupt_return() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_sp(0x0100);
+        eu.set_register16(Register16::CX, 1);
+        // es: rep movsb
+        run(&mut eu, &mut biu, &[0x26, 0xF3, 0xA4], 1);
+        eu.interrupt(0x20, &mut biu);
+        assert_eq!(read_word(&mut biu, 0x300FA), 0x0003);
+    }
+
+    #[test]
+    fn test_jmp_short_near_and_far() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        // jmp short +3; (3 bytes skipped); jmp near 0x0100; ...
+        run(
+            &mut eu,
+            &mut biu,
+            &[0xEB, 0x03, 0, 0, 0, 0xE9, 0xF8, 0x00],
+            1,
+        );
+        assert_eq!(biu.get_instruction_pointer(), 0x0005);
+        run(&mut eu, &mut biu, &[], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0x0100);
+
+        // jmp 0x2222:0x3333
+        run(&mut eu, &mut biu, &[0xEA, 0x33, 0x33, 0x22, 0x22], 1);
+        assert_eq!(biu.get_code_segment_address(), 0x2222);
+        assert_eq!(biu.get_instruction_pointer(), 0x3333);
+    }
+
+    #[test]
+    fn test_jmp_backwards_wraps_within_segment() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        // jmp short -4
+        run(&mut eu, &mut biu, &[0xEB, 0xFC], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0xFFFE);
+    }
+
+    #[test]
+    fn test_jmp_indirect() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::BX, 0x0040);
+        write_word(&mut biu, 0x20040, 0x0010);
+        write_word(&mut biu, 0x20042, 0x1234);
+        // jmp bx
+        run(&mut eu, &mut biu, &[0xFF, 0xE3], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0x0040);
+        // jmp far [bx]
+        run(&mut eu, &mut biu, &[0xFF, 0x2F], 1);
+        assert_eq!(biu.get_code_segment_address(), 0x1234);
+        assert_eq!(biu.get_instruction_pointer(), 0x0010);
+    }
+
+    #[test]
+    fn test_call_and_ret_near() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_sp(0x0100);
+        // call +0x0010
+        run(&mut eu, &mut biu, &[0xE8, 0x10, 0x00], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0x0013);
+        assert_eq!(eu.get_sp(), 0x00FE);
+        assert_eq!(read_word(&mut biu, 0x300FE), 0x0003);
+        // ret 4
+        run(&mut eu, &mut biu, &[0xC2, 0x04, 0x00], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0x0003);
+        assert_eq!(eu.get_sp(), 0x0104);
+    }
+
+    #[test]
+    fn test_call_and_ret_far() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_sp(0x0100);
+        // call 0x1100:0x0000
+        run(&mut eu, &mut biu, &[0x9A, 0x00, 0x00, 0x00, 0x11], 1);
+        assert_eq!(biu.get_code_segment_address(), 0x1100);
+        assert_eq!(biu.get_instruction_pointer(), 0x0000);
+        assert_eq!(read_word(&mut biu, 0x300FC), 0x0005);
+        assert_eq!(read_word(&mut biu, 0x300FE), CODE_SEGMENT);
+        // retf
+        run(&mut eu, &mut biu, &[0xCB], 1);
+        assert_eq!(biu.get_code_segment_address(), CODE_SEGMENT);
+        assert_eq!(biu.get_instruction_pointer(), 0x0005);
+        assert_eq!(eu.get_sp(), 0x0100);
+    }
+
+    #[test]
+    fn test_call_indirect() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_sp(0x0100);
+        write_word(&mut biu, 0x20020, 0x0400);
+        write_word(&mut biu, 0x20022, 0x0600);
+        // call [0x0020]
+        run(&mut eu, &mut biu, &[0xFF, 0x16, 0x20, 0x00], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0x0400);
+        assert_eq!(read_word(&mut biu, 0x300FE), 0x0004);
+        // call far [0x0020]
+        run(&mut eu, &mut biu, &[0xFF, 0x1E, 0x20, 0x00], 1);
+        assert_eq!(biu.get_code_segment_address(), 0x0600);
+        assert_eq!(biu.get_instruction_pointer(), 0x0400);
+        assert_eq!(read_word(&mut biu, 0x300FA), 0x0404);
+    }
+
+    #[test]
+    fn test_conditional_jumps() {
+        // (opcode, carry, zero, sign, overflow, parity, taken)
+        let cases = [
+            (0x70, false, false, false, true, false, true),
+            (0x71, false, false, false, true, false, false),
+            (0x72, true, false, false, false, false, true),
+            (0x73, true, false, false, false, false, false),
+            (0x74, false, true, false, false, false, true),
+            (0x75, false, true, false, false, false, false),
+            (0x76, false, true, false, false, false, true),
+            (0x77, false, false, false, false, false, true),
+            (0x78, false, false, true, false, false, true),
+            (0x79, false, false, true, false, false, false),
+            (0x7A, false, false, false, false, true, true),
+            (0x7B, false, false, false, false, true, false),
+            (0x7C, false, false, true, false, false, true),
+            (0x7D, false, false, true, true, false, true),
+            (0x7E, false, true, false, false, false, true),
+            (0x7F, false, false, true, true, false, true),
+            (0x7F, false, true, false, false, false, false),
+        ];
+        for (opcode, carry, zero, sign, overflow, parity, taken) in cases {
+            // The 0x60 - 0x6F aliases behave identically.
+            for opcode in [opcode, opcode & 0xEF] {
+                let mut bus = AddressBus::new();
+                let mut biu = new_biu(&mut bus);
+                let mut eu = ExecutionUnit::default();
+                let flags = eu.get_flags_mut();
+                flags.set_carry(carry);
+                flags.set_zero(zero);
+                flags.set_sign(sign);
+                flags.set_overflow(overflow);
+                flags.set_parity(parity);
+                run(&mut eu, &mut biu, &[opcode, 0x10], 1);
+                let expected = if taken { 0x0012 } else { 0x0002 };
+                assert_eq!(biu.get_instruction_pointer(), expected, "{opcode:#04x}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_loop_family() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::CX, 3);
+        // inc ax; loop -3
+        run_to(&mut eu, &mut biu, &[0x40, 0xE2, 0xFD], 3);
+        assert_eq!(eu.get_register16(Register16::AX), 3);
+        assert_eq!(eu.get_register16(Register16::CX), 0);
+
+        // jcxz +0x10
+        biu.set_instruction_pointer(0);
+        run(&mut eu, &mut biu, &[0xE3, 0x10], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0x0012);
+
+        // loope exits when ZF is clear, loopne when ZF is set
+        eu.set_register16(Register16::CX, 5);
+        biu.set_instruction_pointer(0);
+        run(&mut eu, &mut biu, &[0xE1, 0x10], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0x0002);
+        assert_eq!(eu.get_register16(Register16::CX), 4);
+        biu.set_instruction_pointer(0);
+        run(&mut eu, &mut biu, &[0xE0, 0x10], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0x0012);
+    }
+
+    #[test]
+    fn test_carry_flag_control() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        // stc; cmc; cmc; clc
+        run(&mut eu, &mut biu, &[0xF9, 0xF5, 0xF5, 0xF8], 1);
+        assert!(eu.get_flags().get_carry());
+        run(&mut eu, &mut biu, &[], 1);
+        assert!(!eu.get_flags().get_carry());
+        run(&mut eu, &mut biu, &[], 1);
+        assert!(eu.get_flags().get_carry());
+        run(&mut eu, &mut biu, &[], 1);
+        assert!(!eu.get_flags().get_carry());
+    }
+
+    #[test]
+    fn test_push_and_pop_registers() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_sp(0x0100);
+        eu.set_register16(Register16::AX, 0x1111);
+        eu.set_register16(Register16::BX, 0x2222);
+        // push ax; push bx; pop cx; pop dx
+        run(&mut eu, &mut biu, &[0x50, 0x53, 0x59, 0x5A], 2);
+        assert_eq!(eu.get_sp(), 0x00FC);
+        assert_eq!(read_word(&mut biu, 0x300FE), 0x1111);
+        assert_eq!(read_word(&mut biu, 0x300FC), 0x2222);
+        run(&mut eu, &mut biu, &[], 2);
+        assert_eq!(eu.get_register16(Register16::CX), 0x2222);
+        assert_eq!(eu.get_register16(Register16::DX), 0x1111);
+        assert_eq!(eu.get_sp(), 0x0100);
+    }
+
+    #[test]
+    fn test_push_sp_pushes_decremented_value() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_sp(0x0100);
+        // push sp
+        run(&mut eu, &mut biu, &[0x54], 1);
+        assert_eq!(read_word(&mut biu, 0x300FE), 0x00FE);
+        // pop sp
+        write_word(&mut biu, 0x300FE, 0x0200);
+        run(&mut eu, &mut biu, &[0x5C], 1);
+        assert_eq!(eu.get_sp(), 0x0200);
+    }
+
+    #[test]
+    fn test_push_and_pop_segments() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_sp(0x0100);
+        // push es; pop ds; push cs; pop es
+        run(&mut eu, &mut biu, &[0x06, 0x1F, 0x0E, 0x07], 4);
+        assert_eq!(biu.get_data_segment_address(), EXTRA_SEGMENT);
+        assert_eq!(biu.get_extra_segment_address(), CODE_SEGMENT);
+    }
+
+    #[test]
+    fn test_pop_cs() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_sp(0x0100);
+        write_word(&mut biu, 0x30100, 0x0800);
+        // pop cs
+        run(&mut eu, &mut biu, &[0x0F], 1);
+        assert_eq!(biu.get_code_segment_address(), 0x0800);
+        assert_eq!(biu.get_instruction_pointer(), 0x0001);
+        assert_eq!(eu.get_sp(), 0x0102);
+    }
+
+    #[test]
+    fn test_push_and_pop_memory() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_sp(0x0100);
+        write_word(&mut biu, 0x20010, 0xABCD);
+        // push word [0x0010]; pop word [0x0020]
+        run(
+            &mut eu,
+            &mut biu,
+            &[0xFF, 0x36, 0x10, 0x00, 0x8F, 0x06, 0x20, 0x00],
+            2,
+        );
+        assert_eq!(read_word(&mut biu, 0x20020), 0xABCD);
+        assert_eq!(eu.get_sp(), 0x0100);
+    }
+
+    #[test]
+    fn test_pushf_and_popf() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_sp(0x0100);
+        eu.get_flags_mut().set_carry(true);
+        eu.get_flags_mut().set_direction(true);
+        // pushf
+        run(&mut eu, &mut biu, &[0x9C], 1);
+        assert_eq!(read_word(&mut biu, 0x300FE), 0xF403);
+
+        // popf of a word with every bit set only sets the defined flags
+        write_word(&mut biu, 0x300FE, 0xFFFF);
+        run(&mut eu, &mut biu, &[0x9D, 0x9C], 2);
+        assert_eq!(read_word(&mut biu, 0x300FE), 0xFFD7);
+        let flags = eu.get_flags();
+        assert!(flags.get_overflow());
+        assert!(flags.get_trap());
+        assert!(flags.get_interrupt_enable());
+    }
+
+    #[test]
+    fn test_int_and_iret() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        write_word(&mut biu, 0x00084, 0x0100);
+        write_word(&mut biu, 0x00086, CODE_SEGMENT);
+        biu.write_byte(0x10100, 0xCF);
+        eu.set_sp(0x0100);
+        eu.get_flags_mut().set_interrupt_enable(true);
+        eu.get_flags_mut().set_carry(true);
+        // int 0x21
+        run(&mut eu, &mut biu, &[0xCD, 0x21], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0x0100);
+        assert!(!eu.get_flags().get_interrupt_enable());
+        assert_eq!(read_word(&mut biu, 0x300FA), 0x0002);
+        // iret
+        run(&mut eu, &mut biu, &[], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0x0002);
+        assert_eq!(eu.get_sp(), 0x0100);
+        assert!(eu.get_flags().get_interrupt_enable());
+        assert!(eu.get_flags().get_carry());
+    }
+
+    #[test]
+    fn test_int3_and_into() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        write_word(&mut biu, 0x0000C, 0x0300);
+        write_word(&mut biu, 0x00010, 0x0400);
+        eu.set_sp(0x0100);
+        // int3
+        run(&mut eu, &mut biu, &[0xCC], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0x0300);
+        assert_eq!(biu.get_code_segment_address(), 0x0000);
+
+        // into does nothing unless OF is set
+        biu.set_code_segment_address(CODE_SEGMENT);
+        biu.set_instruction_pointer(0);
+        run(&mut eu, &mut biu, &[0xCE], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0x0001);
+        eu.get_flags_mut().set_overflow(true);
+        biu.set_instruction_pointer(0);
+        run(&mut eu, &mut biu, &[], 1);
+        assert_eq!(biu.get_instruction_pointer(), 0x0400);
+    }
+
+    #[test]
+    fn test_hlt_until_interrupt() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_sp(0x0100);
+        // hlt
+        run(&mut eu, &mut biu, &[0xF4], 1);
+        assert!(eu.is_halted());
+        eu.interrupt(0x08, &mut biu);
+        assert!(!eu.is_halted());
+        assert_eq!(read_word(&mut biu, 0x300FA), 0x0001);
+    }
+
+    /// Records every write and reads back the low byte of the port number.
+    #[derive(Default)]
+    struct PortLog {
+        writes: Vec<(u16, u8)>,
+    }
+
+    impl IoDevice for PortLog {
+        fn read(&mut self, port: u16) -> u8 {
+            port as u8
+        }
+
+        fn write(&mut self, port: u16, value: u8) {
+            self.writes.push((port, value));
+        }
+    }
+
+    #[test]
+    fn test_every_decodable_instruction_executes() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        // Register and memory forms of every ModR/M reg field
+        let modrms = (0..8u8).flat_map(|reg| [0xC0 | reg << 3, reg << 3 | 0x07]);
+        for opcode in 0..=0xFFu8 {
+            for modrm in modrms.clone() {
+                let program = [opcode, modrm, 0x01, 0x00, 0x01, 0x00];
+                if decode::decode(&mut program.iter()).is_err() {
+                    continue;
+                }
+                eu.set_register16(Register16::CX, 1);
+                biu.set_code_segment_address(CODE_SEGMENT);
+                biu.set_instruction_pointer(0);
+                run(&mut eu, &mut biu, &program, 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_in_and_out() {
+        let mut bus = AddressBus::new();
+        let log = Rc::new(RefCell::new(PortLog::default()));
+        bus.register_io_device(0x0040..=0x0043, Box::new(log.clone()));
+        bus.register_io_device(0x03F8..=0x03FF, Box::new(log.clone()));
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+
+        // in al, 0x42; in ax, 0x40
+        run(&mut eu, &mut biu, &[0xE4, 0x42, 0xE5, 0x40], 1);
+        assert_eq!(eu.get_register8(Register8::AL), 0x42);
+        run(&mut eu, &mut biu, &[0xE5, 0x40], 1);
+        assert_eq!(eu.get_register16(Register16::AX), 0x4140);
+
+        // mov dx, 0x03FA; in al, dx; in ax, dx
+        eu.set_register16(Register16::DX, 0x03FA);
+        run(&mut eu, &mut biu, &[0xEC], 1);
+        assert_eq!(eu.get_register8(Register8::AL), 0xFA);
+        run(&mut eu, &mut biu, &[0xED], 1);
+        assert_eq!(eu.get_register16(Register16::AX), 0xFBFA);
+
+        // Unmapped ports float high.
+        run(&mut eu, &mut biu, &[0xE4, 0x60], 1);
+        assert_eq!(eu.get_register8(Register8::AL), 0xFF);
+
+        // out 0x43, al; out dx, ax
+        eu.set_register16(Register16::AX, 0x1234);
+        run(&mut eu, &mut biu, &[0xE6, 0x43], 1);
+        run(&mut eu, &mut biu, &[0xEF], 1);
+        assert_eq!(
+            log.borrow().writes,
+            [(0x0043, 0x34), (0x03FA, 0x34), (0x03FB, 0x12)]
+        );
+    }
+
+    #[test]
+    fn test_cbw_and_cwd() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.set_register16(Register16::AX, 0x1280);
+        // cbw; cwd
+        run(&mut eu, &mut biu, &[0x98, 0x99], 2);
+        assert_eq!(eu.get_register16(Register16::AX), 0xFF80);
+        assert_eq!(eu.get_register16(Register16::DX), 0xFFFF);
+    }
+
+    #[test]
+    fn test_lahf_and_sahf() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let mut eu = ExecutionUnit::default();
+        eu.get_flags_mut().set_sign(true);
+        eu.get_flags_mut().set_carry(true);
+        eu.get_flags_mut().set_overflow(true);
+        // lahf
+        run(&mut eu, &mut biu, &[0x9F], 1);
+        assert_eq!(eu.get_register8(Register8::AH), 0b1000_0011);
+
+        // mov ah, ZF|AF|PF; sahf
+        run(&mut eu, &mut biu, &[0xB4, 0b0101_0100, 0x9E], 2);
+        let flags = eu.get_flags();
+        assert!(!flags.get_sign());
+        assert!(flags.get_zero());
+        assert!(flags.get_auxiliary_carry());
+        assert!(flags.get_parity());
+        assert!(!flags.get_carry());
+        // SAHF does not touch the high byte of FLAGS.
+        assert!(flags.get_overflow());
+    }
 
     #[test]
     fn test_set_and_get_sp() {
@@ -49,7 +49,8 @@ pub struct Flags {
 }
 
 impl Flags {
-    fn new(
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
         carry: bool,
         parity: bool,
         auxiliary_carry: bool,
@@ -128,6 +129,43 @@ impl Flags {
     pub fn get_trap(&self) -> bool {
         self.trap
     }
+
+    /// Packs the flags into the architectural 16-bit FLAGS layout:
+    ///
+    /// ```text
+    /// 15 14 13 12 11 10  9  8  7  6  5  4  3  2  1  0
+    ///  1  1  1  1 OF DF IF TF SF ZF  0 AF  0 PF  1 CF
+    /// ```
+    ///
+    /// As on the 8086, reserved bits 1 and 12 - 15 read as 1 and bits 3 and 5 as 0.
+    pub fn to_word(&self) -> u16 {
+        0xF002
+            | (self.carry as u16)
+            | (self.parity as u16) << 2
+            | (self.auxiliary_carry as u16) << 4
+            | (self.zero as u16) << 6
+            | (self.sign as u16) << 7
+            | (self.trap as u16) << 8
+            | (self.interrupt_enable as u16) << 9
+            | (self.direction as u16) << 10
+            | (self.overflow as u16) << 11
+    }
+
+    /// Unpacks flags from the architectural 16-bit FLAGS layout (see [`Flags::to_word`]).
+    /// Reserved bits are ignored.
+    pub fn from_word(word: u16) -> Self {
+        Self {
+            carry: word & 1 != 0,
+            parity: word & 1 << 2 != 0,
+            auxiliary_carry: word & 1 << 4 != 0,
+            zero: word & 1 << 6 != 0,
+            sign: word & 1 << 7 != 0,
+            trap: word & 1 << 8 != 0,
+            interrupt_enable: word & 1 << 9 != 0,
+            direction: word & 1 << 10 != 0,
+            overflow: word & 1 << 11 != 0,
+        }
+    }
 }
 #[cfg(test)]
 mod tests {
@@ -227,4 +265,48 @@ mod tests {
         flags.set_trap(true);
         assert!(flags.get_trap());
     }
+
+    #[test]
+    fn test_to_word_reserved_bits() {
+        let flags = Flags::default();
+        assert_eq!(flags.to_word(), 0xF002);
+    }
+
+    #[test]
+    fn test_to_word_bit_positions() {
+        // Bit positions in the order of the `Flags::new` arguments.
+        let bits = [0, 2, 4, 6, 7, 11, 9, 10, 8];
+        for (index, bit) in bits.into_iter().enumerate() {
+            let set = |position| position == index;
+            let flags = Flags::new(
+                set(0),
+                set(1),
+                set(2),
+                set(3),
+                set(4),
+                set(5),
+                set(6),
+                set(7),
+                set(8),
+            );
+            assert_eq!(flags.to_word(), 0xF002 | 1 << bit);
+            assert_eq!(Flags::from_word(1 << bit).to_word(), 0xF002 | 1 << bit);
+        }
+    }
+
+    #[test]
+    fn test_from_word_ignores_reserved_bits() {
+        let flags = Flags::from_word(0xF02A);
+        assert_eq!(flags.to_word(), 0xF002);
+        let flags = Flags::from_word(0xFFFF);
+        assert_eq!(flags.to_word(), 0xFFD7);
+    }
+
+    #[test]
+    fn test_word_round_trip() {
+        for word in 0..=0xFFFFu16 {
+            let defined = word & 0x0FD5;
+            assert_eq!(Flags::from_word(word).to_word(), 0xF002 | defined);
+        }
+    }
 }
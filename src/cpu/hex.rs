@@ -0,0 +1,501 @@
+use std::fmt;
+
+use super::biu::BusInterfaceUnit;
+use super::memory::{ADDRESS_MASK, checksum as sum};
+
+/// Errors raised when loading an Intel HEX or Motorola S-record file. Lines
+/// are numbered from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HexError {
+    /// A line does not start with the record mark (`:` or `S`).
+    MissingRecordMark { line: usize },
+    /// A line holds a character that is not a hex digit, or an odd number of
+    /// them.
+    InvalidDigit { line: usize },
+    /// The record's byte count disagrees with its length, or is too short for
+    /// its type.
+    BadLength { line: usize },
+    /// The record's checksum does not match its contents.
+    BadChecksum {
+        line: usize,
+        expected: u8,
+        actual: u8,
+    },
+    /// The record type is not one the format defines.
+    UnknownRecordType { line: usize, record_type: u8 },
+    /// A data record places bytes past the end of memory.
+    AddressOutOfRange { line: usize },
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HexError::MissingRecordMark { line } => write!(f, "line {line}: missing record mark"),
+            HexError::InvalidDigit { line } => write!(f, "line {line}: invalid hex digits"),
+            HexError::BadLength { line } => write!(f, "line {line}: bad record length"),
+            HexError::BadChecksum {
+                line,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "line {line}: checksum is {actual:#04x}, expected {expected:#04x}"
+            ),
+            HexError::UnknownRecordType { line, record_type } => {
+                write!(f, "line {line}: unknown record type {record_type:02X}")
+            }
+            HexError::AddressOutOfRange { line } => {
+                write!(f, "line {line}: data does not fit in memory")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HexError {}
+
+/// The base address that Intel HEX data records are relative to.
+#[derive(Clone, Copy)]
+enum Base {
+    /// Set by an extended segment address record; offsets wrap at 64K.
+    Segment(u32),
+    /// Set by an extended linear address record.
+    Linear(u32),
+}
+
+/// Loads an Intel HEX file into memory.
+///
+/// Data records are placed relative to the last extended segment address
+/// (type 02), wrapping within the 64K segment, or the last extended linear
+/// address (type 04), running on past 64K. Each of those records replaces
+/// the other, so only the most recent one applies. Data that would land past
+/// the end of memory is an error. A start segment address (type 03) or start
+/// linear address (type 05) sets CS:IP; a linear address is split into a
+/// 64K-aligned segment and an offset. Loading stops at the end-of-file record.
+///
+/// Returns the start address as (CS, IP), if the file has one.
+pub fn load_intel_hex(
+    biu: &mut BusInterfaceUnit,
+    text: &str,
+) -> Result<Option<(u16, u16)>, HexError> {
+    let mut base = Base::Segment(0);
+    let mut start = None;
+    for (index, raw) in text.lines().enumerate() {
+        let line = index + 1;
+        let raw = raw.trim();
+        if raw.is_empty() {
+            continue;
+        }
+        let Some(digits) = raw.strip_prefix(':') else {
+            return Err(HexError::MissingRecordMark { line });
+        };
+        let bytes = parse_bytes(digits, line)?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(HexError::BadLength { line });
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - 1);
+        let expected = sum(body).wrapping_neg();
+        if checksum[0] != expected {
+            return Err(HexError::BadChecksum {
+                line,
+                expected,
+                actual: checksum[0],
+            });
+        }
+
+        let offset = u16::from_be_bytes([body[1], body[2]]);
+        let data = &body[4..];
+        let expect_length = |length: usize| {
+            if data.len() == length {
+                Ok(())
+            } else {
+                Err(HexError::BadLength { line })
+            }
+        };
+        match body[3] {
+            0x00 => {
+                let addresses: Vec<u64> = (0..data.len())
+                    .map(|i| match base {
+                        Base::Segment(segment) => {
+                            segment as u64 + offset.wrapping_add(i as u16) as u64
+                        }
+                        Base::Linear(linear) => linear as u64 + offset as u64 + i as u64,
+                    })
+                    .collect();
+                let memory = biu.get_memory_mut();
+                if addresses
+                    .iter()
+                    .any(|address| *address >= memory.size() as u64)
+                {
+                    return Err(HexError::AddressOutOfRange { line });
+                }
+                for (address, byte) in addresses.into_iter().zip(data) {
+                    memory.load(address as u32, &[*byte]);
+                }
+            }
+            0x01 => break,
+            0x02 => {
+                expect_length(2)?;
+                base = Base::Segment((u16::from_be_bytes([data[0], data[1]]) as u32) << 4);
+            }
+            0x03 => {
+                expect_length(4)?;
+                start = Some((
+                    u16::from_be_bytes([data[0], data[1]]),
+                    u16::from_be_bytes([data[2], data[3]]),
+                ));
+            }
+            0x04 => {
+                expect_length(2)?;
+                base = Base::Linear((u16::from_be_bytes([data[0], data[1]]) as u32) << 16);
+            }
+            0x05 => {
+                expect_length(4)?;
+                start = Some(split_linear(u32::from_be_bytes([
+                    data[0], data[1], data[2], data[3],
+                ])));
+            }
+            record_type => return Err(HexError::UnknownRecordType { line, record_type }),
+        }
+    }
+    set_start(biu, start);
+    Ok(start)
+}
+
+/// Loads a Motorola S-record file into memory.
+///
+/// S1, S2 and S3 data records carry 16-, 24- and 32-bit addresses; S9, S8 and
+/// S7 give the start address in the same widths, which sets CS:IP as for an
+/// Intel HEX start linear address. Header (S0) and count (S5, S6) records are
+/// checked but otherwise ignored. Data that would land past the end of memory
+/// is an error.
+///
+/// Returns the start address as (CS, IP), if the file has one.
+pub fn load_srecord(
+    biu: &mut BusInterfaceUnit,
+    text: &str,
+) -> Result<Option<(u16, u16)>, HexError> {
+    let mut start = None;
+    for (index, raw) in text.lines().enumerate() {
+        let line = index + 1;
+        let raw = raw.trim();
+        if raw.is_empty() {
+            continue;
+        }
+        let Some(record) = raw.strip_prefix('S') else {
+            return Err(HexError::MissingRecordMark { line });
+        };
+        let mut chars = record.chars();
+        let Some(record_type) = chars.next().and_then(|c| c.to_digit(10)) else {
+            return Err(HexError::InvalidDigit { line });
+        };
+        let record_type = record_type as u8;
+        let bytes = parse_bytes(chars.as_str(), line)?;
+        if bytes.is_empty() || bytes.len() != bytes[0] as usize + 1 {
+            return Err(HexError::BadLength { line });
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - 1);
+        let expected = !sum(body);
+        if checksum[0] != expected {
+            return Err(HexError::BadChecksum {
+                line,
+                expected,
+                actual: checksum[0],
+            });
+        }
+
+        let address_length = match record_type {
+            0 | 1 | 5 | 9 => 2,
+            2 | 6 | 8 => 3,
+            3 | 7 => 4,
+            _ => return Err(HexError::UnknownRecordType { line, record_type }),
+        };
+        if body.len() < 1 + address_length {
+            return Err(HexError::BadLength { line });
+        }
+        let address = body[1..=address_length]
+            .iter()
+            .fold(0u32, |address, byte| (address << 8) | *byte as u32);
+        let data = &body[1 + address_length..];
+        match record_type {
+            1..=3 => {
+                let memory = biu.get_memory_mut();
+                if address as u64 + data.len() as u64 > memory.size() as u64 {
+                    return Err(HexError::AddressOutOfRange { line });
+                }
+                memory.load(address, data);
+            }
+            7..=9 => start = Some(split_linear(address)),
+            _ => {}
+        }
+    }
+    set_start(biu, start);
+    Ok(start)
+}
+
+/// Parses pairs of hex digits into bytes.
+fn parse_bytes(digits: &str, line: usize) -> Result<Vec<u8>, HexError> {
+    if !digits.is_ascii() || !digits.len().is_multiple_of(2) {
+        return Err(HexError::InvalidDigit { line });
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16))
+        .collect::<Result<_, _>>()
+        .map_err(|_| HexError::InvalidDigit { line })
+}
+
+/// Splits a linear address into a 64K-aligned segment and an offset.
+fn split_linear(address: u32) -> (u16, u16) {
+    let address = address & ADDRESS_MASK;
+    (((address >> 4) & 0xF000) as u16, address as u16)
+}
+
+fn set_start(biu: &mut BusInterfaceUnit, start: Option<(u16, u16)>) {
+    if let Some((cs, ip)) = start {
+        biu.set_code_segment_address(cs);
+        biu.set_instruction_pointer(ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::bus::AddressBus;
+    use crate::cpu::memory::Memory;
+
+    fn new_biu(bus: &mut AddressBus) -> BusInterfaceUnit<'_> {
+        BusInterfaceUnit::new(0, 0, 0, 0, 0, vec![], bus)
+    }
+
+    #[test]
+    fn test_intel_hex_segmented() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let text = "\
+:020000021000EC
+:0401000090B834126D
+:02FFFF00AABB9B
+:0400000310000100E8
+:00000001FF
+";
+        assert_eq!(load_intel_hex(&mut biu, text), Ok(Some((0x1000, 0x0100))));
+        assert_eq!(biu.get_code_segment_address(), 0x1000);
+        assert_eq!(biu.get_instruction_pointer(), 0x0100);
+        let memory = bus.get_memory();
+        assert_eq!(memory.read(0x10100), 0x90);
+        assert_eq!(memory.read_u16(0x10102), 0x1234);
+        // A record that crosses the end of the segment wraps within it.
+        assert_eq!(memory.read(0x1FFFF), 0xAA);
+        assert_eq!(memory.read(0x10000), 0xBB);
+    }
+
+    #[test]
+    fn test_intel_hex_linear() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let text = "\
+:02000004000FEB
+:02FFF000EA5BCA
+:04000005000FFFF0F9
+:00000001FF
+:01000000AA55
+";
+        assert_eq!(load_intel_hex(&mut biu, text), Ok(Some((0xF000, 0xFFF0))));
+        let memory = bus.get_memory();
+        assert_eq!(memory.read_u16(0xFFFF0), 0x5BEA);
+        // Records after the end-of-file record are ignored.
+        assert_eq!(memory.read(0x00000), 0x00);
+    }
+
+    #[test]
+    fn test_intel_hex_mixed_base_records() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let text = "\
+:020000021000EC
+:0100000011EE
+:020000040002F8
+:0100000022DD
+:020000023000CC
+:0100000033CC
+:00000001FF
+";
+        assert_eq!(load_intel_hex(&mut biu, text), Ok(None));
+        let memory = bus.get_memory();
+        assert_eq!(memory.read(0x10000), 0x11);
+        // Each base record replaces the other rather than adding to it.
+        assert_eq!(memory.read(0x20000), 0x22);
+        assert_eq!(memory.read(0x30000), 0x33);
+        assert_eq!(memory.read(0x40000), 0x00);
+        assert_eq!(memory.read(0x50000), 0x00);
+    }
+
+    #[test]
+    fn test_intel_hex_linear_record_crosses_64k() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let text = "\
+:020000040001F9
+:02FFFF00AABB9B
+:00000001FF
+";
+        assert_eq!(load_intel_hex(&mut biu, text), Ok(None));
+        let memory = bus.get_memory();
+        assert_eq!(memory.read(0x1FFFF), 0xAA);
+        assert_eq!(memory.read(0x20000), 0xBB);
+        assert_eq!(memory.read(0x10000), 0x00);
+    }
+
+    #[test]
+    fn test_intel_hex_address_out_of_range() {
+        // Above 1 MB
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        assert_eq!(
+            load_intel_hex(&mut biu, ":020000040010EA\n:01000000906F"),
+            Err(HexError::AddressOutOfRange { line: 2 })
+        );
+        assert_eq!(bus.get_memory().read(0x00000), 0x00);
+
+        // Past the end of a smaller memory; nothing of the record is written.
+        let mut bus = AddressBus::with_memory(Memory::with_size(0x20000));
+        let mut biu = new_biu(&mut bus);
+        assert_eq!(
+            load_intel_hex(&mut biu, ":020000040001F9\n:02FFFF00AABB9B"),
+            Err(HexError::AddressOutOfRange { line: 2 })
+        );
+        assert_eq!(bus.get_memory().read(0x1FFFF), 0x00);
+    }
+
+    #[test]
+    fn test_intel_hex_without_start_keeps_cs_ip() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        biu.set_code_segment_address(0x2000);
+        assert_eq!(load_intel_hex(&mut biu, ":01000000906F\r\n\r\n"), Ok(None));
+        assert_eq!(biu.get_code_segment_address(), 0x2000);
+        assert_eq!(bus.get_memory().read(0x00000), 0x90);
+    }
+
+    #[test]
+    fn test_intel_hex_errors() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let cases = [
+            (
+                ":01000000906F\n0100000090",
+                HexError::MissingRecordMark { line: 2 },
+            ),
+            (":01000000GG", HexError::InvalidDigit { line: 1 }),
+            (":010000009", HexError::InvalidDigit { line: 1 }),
+            (":0200000090", HexError::BadLength { line: 1 }),
+            (":0100000210ED", HexError::BadLength { line: 1 }),
+            (":0600000000", HexError::BadLength { line: 1 }),
+            (
+                ":00000006FA",
+                HexError::UnknownRecordType {
+                    line: 1,
+                    record_type: 6,
+                },
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(load_intel_hex(&mut biu, text), Err(expected), "{text}");
+        }
+        assert_eq!(
+            load_intel_hex(&mut biu, ":01000000906F\n\n:0100010090FF"),
+            Err(HexError::BadChecksum {
+                line: 3,
+                expected: 0x6E,
+                actual: 0xFF
+            })
+        );
+    }
+
+    #[test]
+    fn test_srecord() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let text = "\
+S00600004844521B
+S1060100B83412FA
+S2060F0000EA5BA5
+S30700010000AABB92
+S5030003F9
+S804010100F9
+";
+        assert_eq!(load_srecord(&mut biu, text), Ok(Some((0x1000, 0x0100))));
+        assert_eq!(biu.get_instruction_pointer(), 0x0100);
+        assert_eq!(biu.get_code_segment_address(), 0x1000);
+        let memory = bus.get_memory();
+        assert_eq!(memory.read(0x00100), 0xB8);
+        assert_eq!(memory.read_u16(0x00101), 0x1234);
+        assert_eq!(memory.read_u16(0xF0000), 0x5BEA);
+        assert_eq!(memory.read_u16(0x10000), 0xBBAA);
+    }
+
+    #[test]
+    fn test_srecord_start_addresses() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        assert_eq!(
+            load_srecord(&mut biu, "S9030100FB"),
+            Ok(Some((0x0000, 0x0100)))
+        );
+        assert_eq!(
+            load_srecord(&mut biu, "S705000FFFF0FC"),
+            Ok(Some((0xF000, 0xFFF0)))
+        );
+        assert_eq!(biu.get_code_segment_address(), 0xF000);
+    }
+
+    #[test]
+    fn test_srecord_address_out_of_range() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        assert_eq!(
+            load_srecord(&mut biu, "S30600100000AA3F"),
+            Err(HexError::AddressOutOfRange { line: 1 })
+        );
+        // The interrupt vector table is left alone.
+        assert_eq!(bus.get_memory().read(0x00000), 0x00);
+
+        let mut bus = AddressBus::with_memory(Memory::with_size(0x10000));
+        let mut biu = new_biu(&mut bus);
+        assert_eq!(
+            load_srecord(&mut biu, "S9030000FC\nS20600FFFFAABB96"),
+            Err(HexError::AddressOutOfRange { line: 2 })
+        );
+        assert_eq!(bus.get_memory().read(0x0FFFF), 0x00);
+    }
+
+    #[test]
+    fn test_srecord_errors() {
+        let mut bus = AddressBus::new();
+        let mut biu = new_biu(&mut bus);
+        let cases = [
+            ("S9030000FC\nX1", HexError::MissingRecordMark { line: 2 }),
+            ("SX030000FC", HexError::InvalidDigit { line: 1 }),
+            ("S1050000FC", HexError::BadLength { line: 1 }),
+            ("S10200FD", HexError::BadLength { line: 1 }),
+            (
+                "S4030000FC",
+                HexError::UnknownRecordType {
+                    line: 1,
+                    record_type: 4,
+                },
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(load_srecord(&mut biu, text), Err(expected), "{text}");
+        }
+        assert_eq!(
+            load_srecord(&mut biu, "S9030000FC\nS1040000900B"),
+            Err(HexError::BadChecksum {
+                line: 2,
+                expected: 0x6B,
+                actual: 0x0B
+            })
+        );
+    }
+}
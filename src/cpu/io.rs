@@ -0,0 +1,166 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+use super::bus::OPEN_BUS;
+
+/// A peripheral attached to the I/O port address space.
+///
+/// Devices are addressed a byte at a time. A word access to port `n` reads or
+/// writes ports `n` and `n + 1`, low byte first.
+pub trait IoDevice {
+    /// Returns the byte the device drives onto the bus when `port` is read.
+    fn read(&mut self, port: u16) -> u8;
+
+    /// Handles a byte written to `port`.
+    fn write(&mut self, port: u16, value: u8);
+}
+
+/// A shared device, so the host can keep a handle to inspect it while it is
+/// attached. [`BusDevice`](super::bus::BusDevice) has the same impl for
+/// memory-mapped devices.
+impl<T: IoDevice> IoDevice for Rc<RefCell<T>> {
+    fn read(&mut self, port: u16) -> u8 {
+        self.borrow_mut().read(port)
+    }
+
+    fn write(&mut self, port: u16, value: u8) {
+        self.borrow_mut().write(port, value);
+    }
+}
+
+/// The 64K I/O port address space, separate from memory.
+#[derive(Default)]
+pub struct IoBus {
+    devices: Vec<(RangeInclusive<u16>, Box<dyn IoDevice>)>,
+}
+
+impl IoBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attaches `device` to the given port range. Where ranges overlap, the
+    /// device registered last answers.
+    pub fn register(&mut self, ports: RangeInclusive<u16>, device: Box<dyn IoDevice>) {
+        self.devices.push((ports, device));
+    }
+
+    /// Reads a byte from `port`. Ports without a device read as [`OPEN_BUS`].
+    pub fn read_byte(&mut self, port: u16) -> u8 {
+        match self.device(port) {
+            Some(device) => device.read(port),
+            None => OPEN_BUS,
+        }
+    }
+
+    /// Writes a byte to `port`. Writes to ports without a device are ignored.
+    pub fn write_byte(&mut self, port: u16, value: u8) {
+        if let Some(device) = self.device(port) {
+            device.write(port, value);
+        }
+    }
+
+    /// Reads a little-endian word from `port` and `port + 1`.
+    pub fn read_word(&mut self, port: u16) -> u16 {
+        let low = self.read_byte(port);
+        let high = self.read_byte(port.wrapping_add(1));
+        u16::from_le_bytes([low, high])
+    }
+
+    /// Writes a little-endian word to `port` and `port + 1`.
+    pub fn write_word(&mut self, port: u16, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        self.write_byte(port, low);
+        self.write_byte(port.wrapping_add(1), high);
+    }
+
+    fn device(&mut self, port: u16) -> Option<&mut Box<dyn IoDevice>> {
+        self.devices
+            .iter_mut()
+            .rev()
+            .find(|(ports, _)| ports.contains(&port))
+            .map(|(_, device)| device)
+    }
+}
+
+impl fmt::Debug for IoBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.devices.iter().map(|(ports, _)| ports))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bank of byte registers starting at `base`.
+    struct Latches {
+        base: u16,
+        values: [u8; 4],
+    }
+
+    impl IoDevice for Latches {
+        fn read(&mut self, port: u16) -> u8 {
+            self.values[(port - self.base) as usize]
+        }
+
+        fn write(&mut self, port: u16, value: u8) {
+            self.values[(port - self.base) as usize] = value;
+        }
+    }
+
+    fn latches(base: u16) -> Rc<RefCell<Latches>> {
+        Rc::new(RefCell::new(Latches {
+            base,
+            values: [0; 4],
+        }))
+    }
+
+    #[test]
+    fn test_unmapped_ports_float() {
+        let mut io = IoBus::new();
+        io.write_byte(0x60, 0x12);
+        assert_eq!(io.read_byte(0x60), OPEN_BUS);
+        assert_eq!(io.read_word(0x60), 0xFFFF);
+    }
+
+    #[test]
+    fn test_byte_access_reaches_device() {
+        let mut io = IoBus::new();
+        let device = latches(0x40);
+        io.register(0x40..=0x43, Box::new(device.clone()));
+        io.write_byte(0x42, 0xA5);
+        assert_eq!(device.borrow().values, [0, 0, 0xA5, 0]);
+        assert_eq!(io.read_byte(0x42), 0xA5);
+        assert_eq!(io.read_byte(0x44), OPEN_BUS);
+    }
+
+    #[test]
+    fn test_word_access_is_little_endian() {
+        let mut io = IoBus::new();
+        let device = latches(0x40);
+        io.register(0x40..=0x43, Box::new(device.clone()));
+        io.write_word(0x40, 0x1234);
+        assert_eq!(device.borrow().values, [0x34, 0x12, 0, 0]);
+        assert_eq!(io.read_word(0x40), 0x1234);
+        // The high byte of a word at the last port is open bus.
+        assert_eq!(io.read_word(0x43), 0xFF00);
+    }
+
+    #[test]
+    fn test_last_registered_device_wins() {
+        let mut io = IoBus::new();
+        let first = latches(0x40);
+        let second = latches(0x42);
+        io.register(0x40..=0x43, Box::new(first.clone()));
+        io.register(0x42..=0x45, Box::new(second.clone()));
+        io.write_byte(0x41, 1);
+        io.write_byte(0x42, 2);
+        assert_eq!(first.borrow().values, [0, 1, 0, 0]);
+        assert_eq!(second.borrow().values, [2, 0, 0, 0]);
+    }
+}
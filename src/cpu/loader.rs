@@ -0,0 +1,526 @@
+use std::fmt;
+
+use super::Cpu;
+use super::decode::Register16;
+use super::memory::physical_address;
+
+/// Size of the Program Segment Prefix that DOS places before a program.
+pub const PSP_SIZE: u16 = 0x100;
+/// Largest .COM image that fits between the PSP and the initial stack word.
+pub const MAX_COM_SIZE: usize = 0xFFFE - PSP_SIZE as usize;
+/// Longest command tail a PSP can hold, leaving room for the length byte and
+/// the terminating carriage return.
+pub const MAX_COMMAND_TAIL: usize = 126;
+/// Initial SP of a .COM program: the top of its segment, less the zero word
+/// DOS pushes so that a near RET lands on the INT 20h at PSP:0000.
+pub const COM_STACK_POINTER: u16 = 0xFFFE;
+
+/// The first segment above conventional memory.
+const CONVENTIONAL_MEMORY_TOP: u16 = 0xA000;
+
+/// Signature at the start of a DOS .EXE file.
+pub const EXE_SIGNATURE: [u8; 2] = *b"MZ";
+/// Size of the fixed part of the MZ header.
+pub const EXE_HEADER_SIZE: usize = 0x1C;
+/// .EXE file sizes are given in 512-byte pages.
+const EXE_PAGE_SIZE: usize = 512;
+
+/// Errors raised when loading a program into memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// The program and its segment do not fit in memory at the load segment.
+    OutOfMemory { segment: u16, length: usize },
+    /// A .COM image is larger than [`MAX_COM_SIZE`]; holds its length.
+    TooLarge(usize),
+    /// The command tail is longer than [`MAX_COMMAND_TAIL`]; holds its length.
+    CommandTailTooLong(usize),
+    /// An .EXE file does not start with the `MZ` signature.
+    NotExecutable,
+    /// The file is shorter than its header says.
+    Truncated { expected: usize, actual: usize },
+    /// A header field is inconsistent; holds a description of the problem.
+    InvalidHeader(&'static str),
+    /// A relocation points outside the load module; holds its index.
+    BadRelocation(usize),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::OutOfMemory { segment, length } => {
+                write!(
+                    f,
+                    "{length} bytes at segment {segment:#06x} do not fit in memory"
+                )
+            }
+            LoadError::TooLarge(length) => {
+                write!(
+                    f,
+                    ".COM image of {length} bytes exceeds {MAX_COM_SIZE} bytes"
+                )
+            }
+            LoadError::CommandTailTooLong(length) => write!(
+                f,
+                "command tail of {length} bytes exceeds {MAX_COMMAND_TAIL} bytes"
+            ),
+            LoadError::NotExecutable => write!(f, "missing MZ signature"),
+            LoadError::Truncated { expected, actual } => {
+                write!(f, "file has {actual} bytes but its header needs {expected}")
+            }
+            LoadError::InvalidHeader(reason) => write!(f, "invalid MZ header: {reason}"),
+            LoadError::BadRelocation(index) => {
+                write!(f, "relocation {index} points outside the load module")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Loads a .COM program at `segment`:0100h and prepares the CPU to run it.
+///
+/// A PSP holding `command_tail` is written at `segment`:0000h. CS, DS, ES and
+/// SS are set to `segment`, IP to 0100h and SP to FFFEh, with a zero word on
+/// the stack so that a near RET terminates the program through the PSP.
+/// Interrupts are enabled, as under DOS.
+pub fn load_com(
+    cpu: &mut Cpu,
+    segment: u16,
+    image: &[u8],
+    command_tail: &str,
+) -> Result<(), LoadError> {
+    if image.len() > MAX_COM_SIZE {
+        return Err(LoadError::TooLarge(image.len()));
+    }
+    // A .COM program owns its whole 64K segment.
+    check_fits(cpu, segment, 0x10000)?;
+    write_psp(cpu, segment, command_tail)?;
+
+    let memory = cpu.biu.get_memory_mut();
+    memory.load(physical_address(segment, PSP_SIZE), image);
+    memory.write_u16(physical_address(segment, COM_STACK_POINTER), 0x0000);
+
+    let biu = &mut cpu.biu;
+    biu.set_code_segment_address(segment);
+    biu.set_data_segment_address(segment);
+    biu.set_extra_segment_address(segment);
+    biu.set_stack_segment_address(segment);
+    biu.set_instruction_pointer(PSP_SIZE);
+    cpu.eu.set_sp(COM_STACK_POINTER);
+    cpu.eu.set_register16(Register16::AX, 0x0000);
+    cpu.eu.get_flags_mut().set_interrupt_enable(true);
+    Ok(())
+}
+
+/// The fields of an MZ header needed to load an .EXE file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExeHeader {
+    /// Size of the file in bytes, header included, as given by the page counts
+    pub file_size: usize,
+    /// Size of the header in bytes; the load module follows it
+    pub header_size: usize,
+    /// Paragraphs needed beyond the load module
+    pub min_extra_paragraphs: u16,
+    /// Paragraphs the program would like beyond the load module
+    pub max_extra_paragraphs: u16,
+    /// Initial SS, relative to the load segment
+    pub ss: u16,
+    /// Initial SP, an absolute offset within the stack segment
+    pub sp: u16,
+    /// Entry point offset within CS
+    pub ip: u16,
+    /// Initial CS, relative to the load segment
+    pub cs: u16,
+    /// Relocations as (offset, segment) pairs, the segment relative to the
+    /// load segment
+    pub relocations: Vec<(u16, u16)>,
+}
+
+impl ExeHeader {
+    /// Parses and validates the MZ header at the start of `file`.
+    pub fn parse(file: &[u8]) -> Result<Self, LoadError> {
+        if file.len() < 2 || file[..2] != EXE_SIGNATURE {
+            return Err(LoadError::NotExecutable);
+        }
+        check_length(file, EXE_HEADER_SIZE)?;
+        let word = |offset: usize| u16::from_le_bytes([file[offset], file[offset + 1]]);
+
+        let last_page_bytes = word(0x02) as usize;
+        let pages = word(0x04) as usize;
+        if pages == 0 {
+            return Err(LoadError::InvalidHeader("page count is zero"));
+        }
+        if last_page_bytes >= EXE_PAGE_SIZE {
+            return Err(LoadError::InvalidHeader("last page is longer than a page"));
+        }
+        let file_size = match last_page_bytes {
+            0 => pages * EXE_PAGE_SIZE,
+            bytes => (pages - 1) * EXE_PAGE_SIZE + bytes,
+        };
+        let header_size = word(0x08) as usize * 16;
+        if header_size < EXE_HEADER_SIZE {
+            return Err(LoadError::InvalidHeader("header is shorter than 28 bytes"));
+        }
+        if header_size > file_size {
+            return Err(LoadError::InvalidHeader("header is longer than the file"));
+        }
+        check_length(file, file_size)?;
+
+        let count = word(0x06) as usize;
+        let table = word(0x18) as usize;
+        if count > 0 && (table < EXE_HEADER_SIZE || table + count * 4 > header_size) {
+            return Err(LoadError::InvalidHeader(
+                "relocation table lies outside the header",
+            ));
+        }
+        let relocations = (0..count)
+            .map(|i| (word(table + i * 4), word(table + i * 4 + 2)))
+            .collect();
+
+        Ok(Self {
+            file_size,
+            header_size,
+            min_extra_paragraphs: word(0x0A),
+            max_extra_paragraphs: word(0x0C),
+            ss: word(0x0E),
+            sp: word(0x10),
+            ip: word(0x14),
+            cs: word(0x16),
+            relocations,
+        })
+    }
+
+    /// Returns the size of the load module: the file less its header.
+    pub fn load_size(&self) -> usize {
+        self.file_size - self.header_size
+    }
+}
+
+/// Loads an .EXE program with its PSP at `psp_segment` and prepares the CPU
+/// to run it.
+///
+/// The load module is copied to the paragraph after the PSP, which becomes
+/// the load segment; every relocation has the load segment added to the word
+/// it points at. CS:IP and SS:SP are taken from the header, relative to the
+/// load segment, and DS and ES point at the PSP. Interrupts are enabled, as
+/// under DOS.
+pub fn load_exe(
+    cpu: &mut Cpu,
+    psp_segment: u16,
+    file: &[u8],
+    command_tail: &str,
+) -> Result<(), LoadError> {
+    let header = ExeHeader::parse(file)?;
+    let module = &file[header.header_size..header.file_size];
+    let load_segment = psp_segment.wrapping_add(PSP_SIZE >> 4);
+
+    let mut image = module.to_vec();
+    for (index, &(offset, segment)) in header.relocations.iter().enumerate() {
+        let address = ((segment as usize) << 4) + offset as usize;
+        let Some(target) = image.get_mut(address..address + 2) else {
+            return Err(LoadError::BadRelocation(index));
+        };
+        let value = u16::from_le_bytes([target[0], target[1]]).wrapping_add(load_segment);
+        target.copy_from_slice(&value.to_le_bytes());
+    }
+
+    let needed = PSP_SIZE as usize + image.len() + header.min_extra_paragraphs as usize * 16;
+    check_fits(cpu, psp_segment, needed)?;
+    write_psp(cpu, psp_segment, command_tail)?;
+    cpu.biu
+        .get_memory_mut()
+        .load(physical_address(load_segment, 0), &image);
+
+    let biu = &mut cpu.biu;
+    biu.set_code_segment_address(load_segment.wrapping_add(header.cs));
+    biu.set_data_segment_address(psp_segment);
+    biu.set_extra_segment_address(psp_segment);
+    biu.set_stack_segment_address(load_segment.wrapping_add(header.ss));
+    biu.set_instruction_pointer(header.ip);
+    cpu.eu.set_sp(header.sp);
+    cpu.eu.set_register16(Register16::AX, 0x0000);
+    cpu.eu.get_flags_mut().set_interrupt_enable(true);
+    Ok(())
+}
+
+/// Returns an error unless `file` holds at least `expected` bytes.
+fn check_length(file: &[u8], expected: usize) -> Result<(), LoadError> {
+    if file.len() < expected {
+        return Err(LoadError::Truncated {
+            expected,
+            actual: file.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Returns an error unless `length` bytes from `segment`:0000h lie within
+/// memory.
+fn check_fits(cpu: &mut Cpu, segment: u16, length: usize) -> Result<(), LoadError> {
+    let start = (segment as usize) << 4;
+    if start + length > cpu.biu.get_memory_mut().size() {
+        return Err(LoadError::OutOfMemory { segment, length });
+    }
+    Ok(())
+}
+
+/// Writes a Program Segment Prefix at `segment`:0000h.
+///
+/// Only the fields programs commonly rely on are filled in: INT 20h at 0000h,
+/// the first segment beyond the program's memory at 0002h, an INT 21h/RETF
+/// dispatcher at 0050h, blank FCBs at 005Ch and 006Ch, and the command tail at
+/// 0080h (length byte, text, carriage return).
+fn write_psp(cpu: &mut Cpu, segment: u16, command_tail: &str) -> Result<(), LoadError> {
+    let tail = command_tail.as_bytes();
+    if tail.len() > MAX_COMMAND_TAIL {
+        return Err(LoadError::CommandTailTooLong(tail.len()));
+    }
+
+    let memory = cpu.biu.get_memory_mut();
+    let top = (memory.size() >> 4).min(CONVENTIONAL_MEMORY_TOP as usize) as u16;
+    let mut psp = [0u8; PSP_SIZE as usize];
+    psp[0x00..0x02].copy_from_slice(&[0xCD, 0x20]);
+    psp[0x02..0x04].copy_from_slice(&top.to_le_bytes());
+    psp[0x50..0x53].copy_from_slice(&[0xCD, 0x21, 0xCB]);
+    psp[0x5D..0x68].fill(b' ');
+    psp[0x6D..0x78].fill(b' ');
+    psp[0x80] = tail.len() as u8;
+    psp[0x81..0x81 + tail.len()].copy_from_slice(tail);
+    psp[0x81 + tail.len()] = b'\r';
+    memory.load(physical_address(segment, 0), &psp);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPUModes;
+    use crate::cpu::bus::AddressBus;
+    use crate::cpu::memory::Memory;
+
+    #[test]
+    fn test_load_com_sets_up_registers() {
+        let mut bus = AddressBus::new();
+        let mut cpu = Cpu::new(CPUModes::Minimum, &mut bus);
+        load_com(&mut cpu, 0x1000, &[0x90], "").unwrap();
+        let biu = cpu.get_biu();
+        assert_eq!(biu.get_code_segment_address(), 0x1000);
+        assert_eq!(biu.get_data_segment_address(), 0x1000);
+        assert_eq!(biu.get_extra_segment_address(), 0x1000);
+        assert_eq!(biu.get_stack_segment_address(), 0x1000);
+        assert_eq!(biu.get_instruction_pointer(), 0x0100);
+        assert_eq!(cpu.get_eu().get_sp(), 0xFFFE);
+        assert!(cpu.get_eu().get_flags().get_interrupt_enable());
+    }
+
+    #[test]
+    fn test_load_com_writes_psp_and_image() {
+        let mut bus = AddressBus::new();
+        let mut cpu = Cpu::new(CPUModes::Minimum, &mut bus);
+        load_com(&mut cpu, 0x1000, &[0xB4, 0x4C, 0xCD, 0x21], " /V FILE.TXT").unwrap();
+        let memory = bus.get_memory();
+        assert_eq!(memory.read_u16(0x10000), 0x20CD);
+        assert_eq!(memory.read_u16(0x10002), 0xA000);
+        assert_eq!(memory.read(0x10050), 0xCD);
+        assert_eq!(memory.read(0x1005D), b' ');
+        assert_eq!(memory.read(0x10080), 12);
+        let tail: Vec<u8> = (0x10081..0x1008E).map(|a| memory.read(a)).collect();
+        assert_eq!(tail, b" /V FILE.TXT\r");
+        assert_eq!(memory.read(0x10100), 0xB4);
+        assert_eq!(memory.read(0x10103), 0x21);
+        assert_eq!(memory.read_u16(0x1FFFE), 0x0000);
+    }
+
+    #[test]
+    fn test_com_program_reads_its_command_tail() {
+        let mut bus = AddressBus::new();
+        let mut cpu = Cpu::new(CPUModes::Minimum, &mut bus);
+        // mov si, 0x0081; lodsb; lodsb; mov cl, [0x0080]; hlt
+        let program = [0xBE, 0x81, 0x00, 0xAC, 0xAC, 0x8A, 0x0E, 0x80, 0x00, 0xF4];
+        load_com(&mut cpu, 0x2000, &program, " ABC").unwrap();
+        cpu.run_until(u64::MAX, |_| false).unwrap();
+        assert_eq!(
+            cpu.get_eu().get_register16(Register16::AX) & 0xFF,
+            b'A' as u16
+        );
+        assert_eq!(cpu.get_eu().get_register16(Register16::CX), 4);
+    }
+
+    #[test]
+    fn test_ret_returns_to_psp() {
+        let mut bus = AddressBus::new();
+        let mut cpu = Cpu::new(CPUModes::Minimum, &mut bus);
+        // ret
+        load_com(&mut cpu, 0x1000, &[0xC3], "").unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_biu().get_instruction_pointer(), 0x0000);
+        assert_eq!(cpu.get_eu().get_sp(), 0x0000);
+    }
+
+    #[test]
+    fn test_memory_top_follows_memory_size() {
+        let mut bus = AddressBus::with_memory(Memory::with_size(0x40000));
+        let mut cpu = Cpu::new(CPUModes::Minimum, &mut bus);
+        load_com(&mut cpu, 0x1000, &[], "").unwrap();
+        assert_eq!(bus.get_memory().read_u16(0x10002), 0x4000);
+    }
+
+    /// Builds an .EXE file with a 32-byte header holding `relocations`, whose
+    /// entry point is CS:IP = 0000:0000 and stack SS:SP = `ss`:0100.
+    fn exe(module: &[u8], relocations: &[(u16, u16)], ss: u16) -> Vec<u8> {
+        let header_paragraphs = (EXE_HEADER_SIZE + relocations.len() * 4).div_ceil(16);
+        let header_size = header_paragraphs * 16;
+        let size = header_size + module.len();
+        let mut file = vec![0u8; size];
+        let mut put = |offset: usize, value: u16| {
+            file[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+        };
+        put(0x00, u16::from_le_bytes(EXE_SIGNATURE));
+        put(0x02, (size % 512) as u16);
+        put(0x04, size.div_ceil(512) as u16);
+        put(0x06, relocations.len() as u16);
+        put(0x08, header_paragraphs as u16);
+        put(0x0A, 0x0010);
+        put(0x0C, 0xFFFF);
+        put(0x0E, ss);
+        put(0x10, 0x0100);
+        put(0x18, EXE_HEADER_SIZE as u16);
+        for (i, &(offset, segment)) in relocations.iter().enumerate() {
+            put(EXE_HEADER_SIZE + i * 4, offset);
+            put(EXE_HEADER_SIZE + i * 4 + 2, segment);
+        }
+        file[header_size..].copy_from_slice(module);
+        file
+    }
+
+    #[test]
+    fn test_parse_exe_header() {
+        let file = exe(&[0x90; 600], &[(0x0001, 0x0000), (0x0010, 0x0002)], 0x0040);
+        let header = ExeHeader::parse(&file).unwrap();
+        assert_eq!(header.header_size, 0x30);
+        assert_eq!(header.file_size, 0x30 + 600);
+        assert_eq!(header.load_size(), 600);
+        assert_eq!(header.ss, 0x0040);
+        assert_eq!(header.sp, 0x0100);
+        assert_eq!(header.min_extra_paragraphs, 0x0010);
+        assert_eq!(header.max_extra_paragraphs, 0xFFFF);
+        assert_eq!(header.relocations, vec![(0x0001, 0x0000), (0x0010, 0x0002)]);
+    }
+
+    #[test]
+    fn test_load_exe_applies_relocations() {
+        let mut bus = AddressBus::new();
+        let mut cpu = Cpu::new(CPUModes::Minimum, &mut bus);
+        // mov ax, seg data; mov ds, ax; mov al, [0x0000]; hlt; data at 0001:0000
+        let mut module = vec![0xB8, 0x01, 0x00, 0x8E, 0xD8, 0xA0, 0x00, 0x00, 0xF4];
+        module.resize(0x10, 0x00);
+        module.extend_from_slice(&[0x5A, 0x00, 0x34, 0x12]);
+        let file = exe(&module, &[(0x0001, 0x0000), (0x0002, 0x0001)], 0x0002);
+        load_exe(&mut cpu, 0x1000, &file, " X").unwrap();
+
+        let biu = cpu.get_biu();
+        assert_eq!(biu.get_code_segment_address(), 0x1010);
+        assert_eq!(biu.get_instruction_pointer(), 0x0000);
+        assert_eq!(biu.get_data_segment_address(), 0x1000);
+        assert_eq!(biu.get_extra_segment_address(), 0x1000);
+        assert_eq!(biu.get_stack_segment_address(), 0x1012);
+        assert_eq!(cpu.get_eu().get_sp(), 0x0100);
+
+        cpu.run_until(u64::MAX, |_| false).unwrap();
+        assert_eq!(cpu.get_eu().get_register16(Register16::AX), 0x105A);
+        assert_eq!(cpu.get_biu().get_data_segment_address(), 0x1011);
+
+        let memory = bus.get_memory();
+        // The PSP comes first, with the command tail.
+        assert_eq!(memory.read_u16(0x10000), 0x20CD);
+        assert_eq!(memory.read(0x10080), 2);
+        assert_eq!(memory.read_u16(0x10101), 0x1011);
+        assert_eq!(memory.read_u16(0x10112), 0x1234 + 0x1010);
+    }
+
+    #[test]
+    fn test_exe_header_errors() {
+        assert_eq!(ExeHeader::parse(b"ZX"), Err(LoadError::NotExecutable));
+        assert_eq!(
+            ExeHeader::parse(b"MZ\x00\x00"),
+            Err(LoadError::Truncated {
+                expected: EXE_HEADER_SIZE,
+                actual: 4
+            })
+        );
+
+        let file = exe(&[0x90; 16], &[], 0);
+        assert_eq!(
+            ExeHeader::parse(&file[..40]),
+            Err(LoadError::Truncated {
+                expected: 48,
+                actual: 40
+            })
+        );
+
+        let mut bad = file.clone();
+        bad[0x04] = 0;
+        assert_eq!(
+            ExeHeader::parse(&bad),
+            Err(LoadError::InvalidHeader("page count is zero"))
+        );
+
+        let mut bad = file.clone();
+        bad[0x08] = 1;
+        assert!(matches!(
+            ExeHeader::parse(&bad),
+            Err(LoadError::InvalidHeader(_))
+        ));
+
+        let mut bad = exe(&[0x90; 16], &[(0, 0)], 0);
+        bad[0x06] = 2;
+        assert!(matches!(
+            ExeHeader::parse(&bad),
+            Err(LoadError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_exe_errors() {
+        let mut bus = AddressBus::with_memory(Memory::with_size(0x20000));
+        let mut cpu = Cpu::new(CPUModes::Minimum, &mut bus);
+        let file = exe(&[0x90; 16], &[(0x000F, 0x0000)], 0);
+        assert_eq!(
+            load_exe(&mut cpu, 0x1000, &file, ""),
+            Err(LoadError::BadRelocation(0))
+        );
+
+        // The module needs 16 bytes plus 16 paragraphs beyond the PSP.
+        let file = exe(&[0x90; 16], &[], 0);
+        assert!(load_exe(&mut cpu, 0x1FDF, &file, "").is_ok());
+        assert_eq!(
+            load_exe(&mut cpu, 0x1FE0, &file, ""),
+            Err(LoadError::OutOfMemory {
+                segment: 0x1FE0,
+                length: 0x210
+            })
+        );
+    }
+
+    #[test]
+    fn test_load_com_errors() {
+        let mut bus = AddressBus::with_memory(Memory::with_size(0x20000));
+        let mut cpu = Cpu::new(CPUModes::Minimum, &mut bus);
+        assert_eq!(
+            load_com(&mut cpu, 0x1000, &vec![0; MAX_COM_SIZE + 1], ""),
+            Err(LoadError::TooLarge(MAX_COM_SIZE + 1))
+        );
+        assert_eq!(
+            load_com(&mut cpu, 0x1001, &[0x90], ""),
+            Err(LoadError::OutOfMemory {
+                segment: 0x1001,
+                length: 0x10000
+            })
+        );
+        assert_eq!(
+            load_com(&mut cpu, 0x1000, &[0x90], &"x".repeat(127)),
+            Err(LoadError::CommandTailTooLong(127))
+        );
+        assert!(load_com(&mut cpu, 0x1000, &vec![0; MAX_COM_SIZE], &"x".repeat(126)).is_ok());
+    }
+}
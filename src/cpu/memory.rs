@@ -1,5 +1,84 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use super::bus::{BusDevice, OPEN_BUS};
+
 const MEMORY_SIZE: usize = 0x0010_0000; // 1 Mb of memory
 
+/// Mask applied to physical addresses; the 8086 has 20 address lines, so
+/// addresses past 0xFFFFF wrap around to 0.
+pub const ADDRESS_MASK: u32 = 0x000F_FFFF;
+
+/// First two bytes of an IBM PC option ROM.
+pub const OPTION_ROM_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+/// Option ROM sizes are given in units of 512 bytes.
+const OPTION_ROM_BLOCK_SIZE: usize = 512;
+
+/// Returns the physical address of `segment:offset`, wrapped to 20 bits.
+pub fn physical_address(segment: u16, offset: u16) -> u32 {
+    (((segment as u32) << 4) + offset as u32) & ADDRESS_MASK
+}
+
+/// Returns the 8-bit sum of `bytes`; a valid ROM image sums to 0.
+pub fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Errors raised when loading a ROM image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomError {
+    /// The image does not fit in memory at the requested address.
+    OutOfRange { address: u32, length: usize },
+    /// An option ROM does not start with 0x55 0xAA.
+    MissingSignature,
+    /// An option ROM is shorter than the size in its header.
+    Truncated { declared: usize, actual: usize },
+    /// An option ROM's header gives a length of zero blocks.
+    EmptyImage,
+    /// The bytes of an option ROM do not sum to zero; holds the actual sum.
+    BadChecksum(u8),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::OutOfRange { address, length } => {
+                write!(f, "{length} bytes at {address:#07x} do not fit in memory")
+            }
+            RomError::MissingSignature => write!(f, "missing 0x55AA option ROM signature"),
+            RomError::Truncated { declared, actual } => {
+                write!(f, "option ROM declares {declared} bytes but has {actual}")
+            }
+            RomError::EmptyImage => write!(f, "option ROM declares a length of 0 blocks"),
+            RomError::BadChecksum(sum) => write!(f, "bad ROM checksum: bytes sum to {sum:#04x}"),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+/// Checks an IBM option ROM image: the 0x55AA signature, the length in
+/// 512-byte blocks at offset 2, and a byte sum of 0 over that length.
+pub fn verify_option_rom(image: &[u8]) -> Result<(), RomError> {
+    if image.len() < 3 || image[..2] != OPTION_ROM_SIGNATURE {
+        return Err(RomError::MissingSignature);
+    }
+    let declared = image[2] as usize * OPTION_ROM_BLOCK_SIZE;
+    if declared == 0 {
+        return Err(RomError::EmptyImage);
+    }
+    if image.len() < declared {
+        return Err(RomError::Truncated {
+            declared,
+            actual: image.len(),
+        });
+    }
+    match checksum(&image[..declared]) {
+        0 => Ok(()),
+        sum => Err(RomError::BadChecksum(sum)),
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Memory {
     /// The memory array that stores the data.
@@ -7,19 +86,314 @@ pub struct Memory {
     /// The memory array is indexed by a 16-bit (word) address.
     /// Stored in little-endian format. (least significant byte first)
     data: Vec<u8>,
+    /// Write-protected regions, such as loaded ROM images
+    rom: Vec<RangeInclusive<u32>>,
+    /// Number of writes ignored because they hit a ROM region
+    rom_writes: u64,
 }
 
 impl Memory {
     pub fn new() -> Self {
+        Self::with_size(MEMORY_SIZE)
+    }
+
+    /// Creates `size` bytes of zeroed memory starting at address 0. Addresses
+    /// past the end read as open bus and ignore writes.
+    pub fn with_size(size: usize) -> Self {
         Self {
-            data: vec![0u8; MEMORY_SIZE],
+            data: vec![0u8; size.min(MEMORY_SIZE)],
+            rom: Vec::new(),
+            rom_writes: 0,
         }
     }
+
+    /// Returns the number of bytes of memory.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
     pub fn read(&self, address: u32) -> u8 {
-        self.data[address as usize]
+        match self.data.get((address & ADDRESS_MASK) as usize) {
+            Some(byte) => *byte,
+            None => OPEN_BUS,
+        }
     }
 
+    /// Writes a byte. Writes to ROM are ignored and counted.
     pub fn write(&mut self, address: u32, value: u8) {
-        self.data[address as usize] = value;
+        let address = address & ADDRESS_MASK;
+        if self.is_read_only(address) {
+            self.rom_writes += 1;
+            return;
+        }
+        if let Some(byte) = self.data.get_mut(address as usize) {
+            *byte = value;
+        }
+    }
+
+    /// Copies `bytes` into memory starting at `address`, ignoring write
+    /// protection. Addresses wrap at 1 MB.
+    pub fn load(&mut self, address: u32, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            let address = address.wrapping_add(i as u32) & ADDRESS_MASK;
+            if let Some(slot) = self.data.get_mut(address as usize) {
+                *slot = *byte;
+            }
+        }
+    }
+
+    /// Loads a ROM image at `address` and write-protects it.
+    pub fn load_rom(&mut self, address: u32, image: &[u8]) -> Result<(), RomError> {
+        let end = address as usize + image.len();
+        if image.is_empty() || end > self.data.len() {
+            return Err(RomError::OutOfRange {
+                address,
+                length: image.len(),
+            });
+        }
+        self.load(address, image);
+        self.set_read_only(address..=(end - 1) as u32);
+        Ok(())
+    }
+
+    /// Verifies an option ROM image with [`verify_option_rom`], then loads it
+    /// at `address` and write-protects it.
+    pub fn load_option_rom(&mut self, address: u32, image: &[u8]) -> Result<(), RomError> {
+        verify_option_rom(image)?;
+        self.load_rom(address, image)
+    }
+
+    /// Write-protects the given range of addresses. This is the mechanism for
+    /// ROM images copied into system memory, such as a BIOS loaded by a host
+    /// before boot; a ROM that lives on its own device should be mapped with
+    /// [`AddressBus::map_read_only_device`](super::bus::AddressBus::map_read_only_device) instead.
+    pub fn set_read_only(&mut self, range: RangeInclusive<u32>) {
+        self.rom.push(range);
+    }
+
+    /// Returns true if `address` is in a write-protected region.
+    pub fn is_read_only(&self, address: u32) -> bool {
+        self.rom.iter().any(|range| range.contains(&address))
+    }
+
+    /// Returns the number of writes ignored because they hit ROM.
+    pub fn get_rom_write_count(&self) -> u64 {
+        self.rom_writes
+    }
+
+    /// Reads a little-endian word. The high byte of a word at 0xFFFFF comes from 0x00000.
+    pub fn read_u16(&self, address: u32) -> u16 {
+        u16::from_le_bytes([self.read(address), self.read(address.wrapping_add(1))])
+    }
+
+    /// Writes a little-endian word. The high byte of a word at 0xFFFFF goes to 0x00000.
+    pub fn write_u16(&mut self, address: u32, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        self.write(address, low);
+        self.write(address.wrapping_add(1), high);
+    }
+
+    /// Reads the byte at `segment:offset`.
+    pub fn read_segmented(&self, segment: u16, offset: u16) -> u8 {
+        self.read(physical_address(segment, offset))
+    }
+
+    /// Writes the byte at `segment:offset`.
+    pub fn write_segmented(&mut self, segment: u16, offset: u16, value: u8) {
+        self.write(physical_address(segment, offset), value);
+    }
+
+    /// Reads a word at `segment:offset`. As on the 8086, the offset of the
+    /// high byte wraps within the segment, so a word at offset 0xFFFF has
+    /// its high byte at offset 0x0000.
+    pub fn read_u16_segmented(&self, segment: u16, offset: u16) -> u16 {
+        u16::from_le_bytes([
+            self.read_segmented(segment, offset),
+            self.read_segmented(segment, offset.wrapping_add(1)),
+        ])
+    }
+
+    /// Writes a word at `segment:offset`, wrapping within the segment.
+    pub fn write_u16_segmented(&mut self, segment: u16, offset: u16, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        self.write_segmented(segment, offset, low);
+        self.write_segmented(segment, offset.wrapping_add(1), high);
+    }
+}
+
+/// Memory can also be mapped as a device, e.g. for video RAM; it is then
+/// addressed relative to the start of its mapping.
+impl BusDevice for Memory {
+    fn read(&mut self, offset: u32) -> u8 {
+        Memory::read(self, offset)
+    }
+
+    fn write(&mut self, offset: u32, value: u8) {
+        Memory::write(self, offset, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_and_write() {
+        let mut memory = Memory::new();
+        memory.write(0x12345, 0xAB);
+        assert_eq!(memory.read(0x12345), 0xAB);
+    }
+
+    #[test]
+    fn test_past_the_end_is_open_bus() {
+        let mut memory = Memory::with_size(0xA0000);
+        assert_eq!(memory.size(), 0xA0000);
+        memory.write(0x9FFFF, 0x12);
+        memory.write(0xA0000, 0x34);
+        assert_eq!(memory.read(0x9FFFF), 0x12);
+        assert_eq!(memory.read(0xA0000), OPEN_BUS);
+    }
+
+    #[test]
+    fn test_load() {
+        let mut memory = Memory::new();
+        memory.load(0x00100, &[1, 2, 3]);
+        assert_eq!(memory.read(0x00100), 1);
+        assert_eq!(memory.read(0x00102), 3);
+        // Loading wraps at 1 MB.
+        memory.load(0xFFFFF, &[4, 5]);
+        assert_eq!(memory.read(0xFFFFF), 4);
+        assert_eq!(memory.read(0x00000), 5);
+    }
+
+    #[test]
+    fn test_rom_is_write_protected() {
+        let mut memory = Memory::new();
+        let image = vec![0xEA; 0x10000];
+        memory.load_rom(0xF0000, &image).unwrap();
+        assert_eq!(memory.read(0xFFFF0), 0xEA);
+        memory.write(0xF1234, 0x00);
+        memory.write_u16(0xEFFFF, 0x1234);
+        assert_eq!(memory.read(0xF1234), 0xEA);
+        assert_eq!(memory.read(0xEFFFF), 0x34);
+        assert_eq!(memory.read(0xF0000), 0xEA);
+        assert_eq!(memory.get_rom_write_count(), 2);
+        assert!(memory.is_read_only(0xF0000));
+        assert!(!memory.is_read_only(0xEFFFF));
+    }
+
+    #[test]
+    fn test_rom_must_fit() {
+        let mut memory = Memory::new();
+        assert_eq!(
+            memory.load_rom(0xFFFF0, &[0; 0x20]),
+            Err(RomError::OutOfRange {
+                address: 0xFFFF0,
+                length: 0x20
+            })
+        );
+        let mut memory = Memory::with_size(0xA0000);
+        assert!(memory.load_rom(0xF0000, &[0; 0x10]).is_err());
+    }
+
+    /// Builds a valid option ROM of `blocks` 512-byte blocks.
+    fn option_rom(blocks: u8) -> Vec<u8> {
+        let mut image = vec![0u8; blocks as usize * 512];
+        image[..3].copy_from_slice(&[0x55, 0xAA, blocks]);
+        image[3] = 0xCB; // retf
+        let last = image.len() - 1;
+        image[last] = 0u8.wrapping_sub(checksum(&image));
+        image
+    }
+
+    #[test]
+    fn test_verify_option_rom() {
+        let image = option_rom(4);
+        assert_eq!(checksum(&image), 0);
+        assert_eq!(verify_option_rom(&image), Ok(()));
+
+        let mut bad = image.clone();
+        bad[0x10] ^= 0x01;
+        assert_eq!(verify_option_rom(&bad), Err(RomError::BadChecksum(0x01)));
+
+        let mut unsigned = image.clone();
+        unsigned[1] = 0x00;
+        assert_eq!(
+            verify_option_rom(&unsigned),
+            Err(RomError::MissingSignature)
+        );
+
+        assert_eq!(
+            verify_option_rom(&image[..1024]),
+            Err(RomError::Truncated {
+                declared: 2048,
+                actual: 1024
+            })
+        );
+
+        // A zero block count would otherwise checksum an empty slice to 0.
+        let mut empty = image.clone();
+        empty[2] = 0x00;
+        assert_eq!(verify_option_rom(&empty), Err(RomError::EmptyImage));
+        assert_eq!(
+            verify_option_rom(&[0x55, 0xAA, 0x00]),
+            Err(RomError::EmptyImage)
+        );
+    }
+
+    #[test]
+    fn test_load_option_rom() {
+        let mut memory = Memory::new();
+        let image = option_rom(1);
+        memory.load_option_rom(0xC8000, &image).unwrap();
+        assert_eq!(memory.read_u16(0xC8000), 0xAA55);
+        assert!(memory.is_read_only(0xC81FF));
+        assert!(!memory.is_read_only(0xC8200));
+
+        let mut bad = image;
+        bad[5] = 0xFF;
+        assert!(memory.load_option_rom(0xD0000, &bad).is_err());
+        assert_eq!(memory.read(0xD0000), 0x00);
+    }
+
+    #[test]
+    fn test_address_wraps_at_1mb() {
+        let mut memory = Memory::new();
+        memory.write(0x100000, 0x5A);
+        assert_eq!(memory.read(0x00000), 0x5A);
+        assert_eq!(physical_address(0xFFFF, 0x0010), 0x00000);
+        assert_eq!(physical_address(0xFFFF, 0xFFFF), 0x0FFEF);
+    }
+
+    #[test]
+    fn test_read_and_write_u16() {
+        let mut memory = Memory::new();
+        memory.write_u16(0x01000, 0x1234);
+        assert_eq!(memory.read(0x01000), 0x34);
+        assert_eq!(memory.read(0x01001), 0x12);
+        assert_eq!(memory.read_u16(0x01000), 0x1234);
+
+        // Odd addresses work the same, just slower on real hardware.
+        memory.write_u16(0x01003, 0xABCD);
+        assert_eq!(memory.read_u16(0x01003), 0xABCD);
+    }
+
+    #[test]
+    fn test_u16_wraps_at_1mb() {
+        let mut memory = Memory::new();
+        memory.write_u16(0xFFFFF, 0xBEEF);
+        assert_eq!(memory.read(0xFFFFF), 0xEF);
+        assert_eq!(memory.read(0x00000), 0xBE);
+        assert_eq!(memory.read_u16(0xFFFFF), 0xBEEF);
+    }
+
+    #[test]
+    fn test_u16_segmented_wraps_within_segment() {
+        let mut memory = Memory::new();
+        memory.write_u16_segmented(0x2000, 0xFFFF, 0x4321);
+        assert_eq!(memory.read(0x2FFFF), 0x21);
+        assert_eq!(memory.read(0x20000), 0x43);
+        assert_eq!(memory.read(0x30000), 0x00);
+        assert_eq!(memory.read_u16_segmented(0x2000, 0xFFFF), 0x4321);
     }
 }
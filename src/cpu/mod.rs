@@ -1,12 +1,20 @@
+pub mod alu;
 pub mod biu;
 pub mod bus;
+pub mod decode;
+pub mod disasm;
+pub mod ea;
 pub mod eu;
 pub mod flags;
+pub mod hex;
+pub mod io;
+pub mod loader;
 pub mod memory;
 pub mod registers;
-/// Represents a 16-bit register with methods to access and modify the low and high bytes.
+pub mod timing;
+use decode::DecodeError;
 
-enum CPUModes {
+pub enum CPUModes {
     /// The cpu provide bus control signals needed for memory and I/O operations.
     Minimum,
 
@@ -19,7 +27,7 @@ enum CPUModes {
 }
 
 /// Represents the Intel 8086 CPU with its registers and segments.
-struct CPU<'a> {
+pub struct Cpu<'a> {
     /// Mode of the CPU
     /// The mode of the CPU determines the number of control lines used to interface with the system bus.
     ///
@@ -33,4 +41,718 @@ struct CPU<'a> {
 
     eu: eu::ExecutionUnit,
     biu: biu::BusInterfaceUnit<'a>,
+
+    /// Latched by a rising edge on the NMI pin until the interrupt is serviced.
+    nmi_pending: bool,
+    /// The vector supplied by the interrupt controller while the INTR pin is asserted.
+    intr: Option<u8>,
+
+    /// Clocks elapsed since the last reset.
+    cycles: u64,
+}
+
+impl<'a> Cpu<'a> {
+    pub fn new(mode: CPUModes, bus: &'a mut bus::AddressBus) -> Self {
+        let mut cpu = Self {
+            mode,
+            eu: eu::ExecutionUnit::default(),
+            biu: biu::BusInterfaceUnit::new(0, 0, 0, 0, 0, vec![], bus),
+            nmi_pending: false,
+            intr: None,
+            cycles: 0,
+        };
+        cpu.reset();
+        cpu
+    }
+
+    /// Resets the CPU, as if the RESET pin had been asserted.
+    ///
+    /// The BIU and EU enter their power-on state, so execution starts at
+    /// FFFF:0000 (physical FFFF0h) with interrupts disabled. Pending interrupts
+    /// are dropped and the cycle counter starts again from zero. Memory is left
+    /// untouched.
+    pub fn reset(&mut self) {
+        self.eu.reset();
+        self.biu.reset();
+        self.nmi_pending = false;
+        self.intr = None;
+        self.cycles = 0;
+    }
+
+    pub fn get_mode(&self) -> &CPUModes {
+        &self.mode
+    }
+
+    pub fn get_eu(&self) -> &eu::ExecutionUnit {
+        &self.eu
+    }
+    pub fn get_eu_mut(&mut self) -> &mut eu::ExecutionUnit {
+        &mut self.eu
+    }
+
+    pub fn get_biu(&self) -> &biu::BusInterfaceUnit<'a> {
+        &self.biu
+    }
+    pub fn get_biu_mut(&mut self) -> &mut biu::BusInterfaceUnit<'a> {
+        &mut self.biu
+    }
+
+    /// Returns the number of clocks elapsed since the last reset.
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Signals a rising edge on the NMI pin.
+    ///
+    /// The non-maskable interrupt is serviced at the next instruction boundary
+    /// regardless of IF.
+    pub fn raise_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    /// Asserts the INTR pin; `vector` is the type number the interrupt
+    /// controller supplies during the interrupt acknowledge cycle.
+    ///
+    /// The request is serviced at an instruction boundary while IF is set, and
+    /// is released once acknowledged.
+    pub fn request_interrupt(&mut self, vector: u8) {
+        self.intr = Some(vector);
+    }
+
+    /// Deasserts the INTR pin without the request being serviced.
+    pub fn clear_interrupt_request(&mut self) {
+        self.intr = None;
+    }
+
+    /// Executes one instruction, then services any pending interrupt.
+    ///
+    /// The BIU supplies the instruction bytes to the decoder from its prefetch
+    /// queue, then refills the queue while the EU executes the result. As on
+    /// real hardware, an instruction that modifies code already in the queue
+    /// does not affect the bytes that will be executed. While halted, no
+    /// instruction is executed but interrupts are still serviced.
+    ///
+    /// Returns the number of clocks taken, including any interrupt entry, the
+    /// extra bus cycles of word transfers at odd addresses and device wait
+    /// states.
+    pub fn step(&mut self) -> Result<u32, DecodeError> {
+        // The trap flag is sampled before the instruction runs, so the instruction
+        // that sets TF is not trapped; the one after it is.
+        let mut trap = false;
+        let mut inhibit = false;
+        let mut shadow = false;
+        let mut clocks = timing::HALTED_CLOCKS;
+        if !self.eu.is_halted() {
+            trap = self.eu.get_flags().get_trap();
+            let instruction = decode::decode(&mut self.biu)?;
+            self.biu.fill_instruction_queue();
+            clocks = self.eu.execute(&instruction, &mut self.biu);
+            inhibit = loads_segment_register(&instruction);
+            shadow = instruction.mnemonic == decode::Mnemonic::Sti;
+        }
+        // After a segment register load (e.g. MOV SS, POP SS) the 8086 holds off
+        // all interrupts, including single-step, until the next instruction has
+        // run, so that SS:SP can be changed as a pair.
+        // STI only lets INTR in after the following instruction, so that
+        // `sti; iret` and `sti; ret` return before a pending request is taken.
+        if !inhibit {
+            clocks += self.service_interrupts(trap, !shadow);
+        }
+        clocks += self.biu.take_bus_clocks();
+        self.cycles += clocks as u64;
+        Ok(clocks)
+    }
+
+    /// Steps until `stop` returns true or at least `cycle_budget` clocks have
+    /// elapsed, whichever comes first.
+    ///
+    /// `stop` is checked before every instruction, so a predicate that is
+    /// already true runs nothing. Running also ends when the CPU is halted with
+    /// no interrupt pending that could wake it. Returns the number of clocks run.
+    pub fn run_until(
+        &mut self,
+        cycle_budget: u64,
+        mut stop: impl FnMut(&Self) -> bool,
+    ) -> Result<u64, DecodeError> {
+        let start = self.cycles;
+        while self.cycles - start < cycle_budget && !stop(self) {
+            if self.eu.is_halted() && !self.interrupt_pending() {
+                break;
+            }
+            self.step()?;
+        }
+        Ok(self.cycles - start)
+    }
+
+    /// Returns true if NMI, or INTR while IF is set, would be serviced at the
+    /// next instruction boundary.
+    fn interrupt_pending(&self) -> bool {
+        self.nmi_pending || (self.eu.get_flags().get_interrupt_enable() && self.intr.is_some())
+    }
+
+    /// Services the highest-priority pending interrupt, if any, then the
+    /// single-step trap when `trap` is set, and returns the clocks taken to
+    /// enter their handlers.
+    ///
+    /// Internal interrupts (divide error, INT n, INTO) are raised by the
+    /// instruction itself and so always come first; then NMI, or INTR when IF
+    /// is set and `allow_intr` is true. The trap was sampled before the
+    /// instruction, so it is still taken after either of them: its frame
+    /// returns to the first instruction of their handler, whose own frame
+    /// holds the original flags with TF set.
+    fn service_interrupts(&mut self, trap: bool, allow_intr: bool) -> u32 {
+        let mut clocks = if self.nmi_pending {
+            self.nmi_pending = false;
+            self.eu.interrupt(eu::NMI_VECTOR, &mut self.biu);
+            timing::NMI_CLOCKS
+        } else if allow_intr
+            && self.eu.get_flags().get_interrupt_enable()
+            && let Some(vector) = self.intr.take()
+        {
+            self.eu.interrupt(vector, &mut self.biu);
+            timing::INTR_CLOCKS
+        } else {
+            0
+        };
+        if trap {
+            self.eu.interrupt(eu::SINGLE_STEP_VECTOR, &mut self.biu);
+            clocks += timing::NMI_CLOCKS;
+        }
+        clocks
+    }
+}
+
+/// Returns true if the instruction writes a segment register (MOV sreg, POP sreg).
+fn loads_segment_register(instruction: &decode::Instruction) -> bool {
+    matches!(
+        instruction.mnemonic,
+        decode::Mnemonic::Mov | decode::Mnemonic::Pop
+    ) && matches!(instruction.destination, Some(decode::Operand::Segment(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `bytes` to memory starting at the physical `address`.
+    fn load(cpu: &mut Cpu, address: u32, bytes: &[u8]) {
+        for (offset, byte) in bytes.iter().enumerate() {
+            cpu.biu.write_byte(address + offset as u32, *byte);
+        }
+    }
+
+    fn read_word(cpu: &mut Cpu, address: u32) -> u16 {
+        u16::from_le_bytes([cpu.biu.read_byte(address), cpu.biu.read_byte(address + 1)])
+    }
+
+    /// Sets up a CPU with its stack at 0x3000:0x0100, IF set, and handlers
+    /// for vectors 1 (single-step), 2 (NMI) and 0x08 that are a single IRET.
+    fn new_cpu(bus: &mut bus::AddressBus) -> Cpu<'_> {
+        let mut cpu = Cpu::new(CPUModes::Minimum, bus);
+        cpu.biu.set_code_segment_address(0x1000);
+        cpu.biu.set_stack_segment_address(0x3000);
+        cpu.eu.set_sp(0x0100);
+        cpu.eu.get_flags_mut().set_interrupt_enable(true);
+        // Single-step handler at 0x0000:0x0400, NMI handler at 0x0000:0x0500,
+        // INT 8 handler at 0x0000:0x0600
+        load(&mut cpu, 0x00004, &[0x00, 0x04, 0x00, 0x00]);
+        load(&mut cpu, 0x00008, &[0x00, 0x05, 0x00, 0x00]);
+        load(&mut cpu, 0x00400, &[0xCF]);
+        load(&mut cpu, 0x00020, &[0x00, 0x06, 0x00, 0x00]);
+        load(&mut cpu, 0x00500, &[0xCF]);
+        load(&mut cpu, 0x00600, &[0xCF]);
+        cpu
+    }
+
+    #[test]
+    fn test_intr_is_serviced_after_the_current_instruction() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        // nop; nop
+        load(&mut cpu, 0x10000, &[0x90, 0x90]);
+        cpu.request_interrupt(0x08);
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_code_segment_address(), 0x0000);
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0600);
+        assert!(!cpu.eu.get_flags().get_interrupt_enable());
+        assert_eq!(read_word(&mut cpu, 0x300FA), 0x0001);
+        assert_eq!(read_word(&mut cpu, 0x300FC), 0x1000);
+
+        // The request was acknowledged, so IRET returns to the program.
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_code_segment_address(), 0x1000);
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0001);
+        assert!(cpu.eu.get_flags().get_interrupt_enable());
+    }
+
+    #[test]
+    fn test_intr_is_masked_by_interrupt_flag() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        // cli; nop; sti; nop
+        load(&mut cpu, 0x10000, &[0xFA, 0x90, 0xFB, 0x90]);
+        cpu.request_interrupt(0x08);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0002);
+        // The instruction after STI runs before the request is taken.
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0003);
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0600);
+        assert_eq!(read_word(&mut cpu, 0x300FA), 0x0004);
+    }
+
+    #[test]
+    fn test_sti_hlt_waits_for_intr() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        cpu.eu.get_flags_mut().set_interrupt_enable(false);
+        // sti; hlt
+        load(&mut cpu, 0x10000, &[0xFB, 0xF4]);
+        cpu.request_interrupt(0x08);
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0600);
+        assert_eq!(read_word(&mut cpu, 0x300FA), 0x0002);
+    }
+
+    #[test]
+    fn test_nmi_ignores_interrupt_flag() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        cpu.eu.get_flags_mut().set_interrupt_enable(false);
+        load(&mut cpu, 0x10000, &[0x90]);
+        cpu.raise_nmi();
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0500);
+    }
+
+    #[test]
+    fn test_nmi_has_priority_over_intr() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        load(&mut cpu, 0x10000, &[0x90]);
+        cpu.request_interrupt(0x08);
+        cpu.raise_nmi();
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0500);
+
+        // INTR is still pending, but the NMI handler runs with IF clear.
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0600);
+        assert_eq!(read_word(&mut cpu, 0x300FA), 0x0001);
+    }
+
+    #[test]
+    fn test_internal_interrupt_comes_before_intr() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        // INT 0x21 handler at 0x0000:0x0700
+        load(&mut cpu, 0x00084, &[0x00, 0x07, 0x00, 0x00]);
+        load(&mut cpu, 0x10000, &[0xCD, 0x21]);
+        cpu.request_interrupt(0x08);
+        cpu.step().unwrap();
+        // INT 0x21 cleared IF, so INTR waits for the handler to return.
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0700);
+        assert_eq!(read_word(&mut cpu, 0x300FA), 0x0002);
+    }
+
+    #[test]
+    fn test_halt_resumes_on_interrupt() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        // hlt; nop
+        load(&mut cpu, 0x10000, &[0xF4, 0x90]);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert!(cpu.eu.is_halted());
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0001);
+
+        cpu.request_interrupt(0x08);
+        cpu.step().unwrap();
+        assert!(!cpu.eu.is_halted());
+        // iret returns past the HLT
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0001);
+    }
+
+    #[test]
+    fn test_clear_interrupt_request() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        load(&mut cpu, 0x10000, &[0x90]);
+        cpu.request_interrupt(0x08);
+        cpu.clear_interrupt_request();
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0001);
+    }
+
+    /// Sets TF through POPF, as a debugger returning to the program would.
+    /// The program is: push 0x0100 (via AX); popf; nop; nop; nop
+    fn load_trap_program(cpu: &mut Cpu) {
+        load(
+            cpu,
+            0x10000,
+            &[0xB8, 0x00, 0x01, 0x50, 0x9D, 0x90, 0x90, 0x90],
+        );
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert!(cpu.eu.get_flags().get_trap());
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0005);
+    }
+
+    #[test]
+    fn test_trap_after_instruction_following_popf() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        cpu.eu.get_flags_mut().set_interrupt_enable(false);
+        load_trap_program(&mut cpu);
+
+        // The POPF itself was not trapped; the NOP after it is.
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_code_segment_address(), 0x0000);
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0400);
+        assert!(!cpu.eu.get_flags().get_trap());
+        assert_eq!(read_word(&mut cpu, 0x300FA), 0x0006);
+        assert_eq!(read_word(&mut cpu, 0x300FE) & 0x0100, 0x0100);
+
+        // The handler is not single-stepped; IRET restores TF and the next
+        // instruction traps again.
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0006);
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0400);
+        assert_eq!(read_word(&mut cpu, 0x300FA), 0x0007);
+    }
+
+    #[test]
+    fn test_trap_suppressed_after_mov_ss() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        cpu.eu.get_flags_mut().set_interrupt_enable(false);
+        cpu.eu.get_flags_mut().set_trap(true);
+        // mov ss, ax; mov sp, 0x0100; nop
+        load(&mut cpu, 0x10000, &[0x8E, 0xD0, 0xBC, 0x00, 0x01, 0x90]);
+        cpu.eu.set_register16(decode::Register16::AX, 0x3000);
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_code_segment_address(), 0x1000);
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0002);
+
+        // The instruction after MOV SS is trapped as usual.
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0400);
+        assert_eq!(read_word(&mut cpu, 0x300FA), 0x0005);
+    }
+
+    #[test]
+    fn test_trap_suppressed_after_pop_ss() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        cpu.eu.get_flags_mut().set_interrupt_enable(false);
+        cpu.eu.get_flags_mut().set_trap(true);
+        load(&mut cpu, 0x30100, &[0x00, 0x30]);
+        // pop ss; nop
+        load(&mut cpu, 0x10000, &[0x17, 0x90]);
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0400);
+    }
+
+    #[test]
+    fn test_intr_has_priority_over_trap() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        cpu.eu.get_flags_mut().set_trap(true);
+        load(&mut cpu, 0x10000, &[0x90]);
+        cpu.request_interrupt(0x08);
+        assert_eq!(
+            cpu.step().unwrap(),
+            3 + timing::INTR_CLOCKS + timing::NMI_CLOCKS
+        );
+
+        // The INTR frame returns to the program with TF still set.
+        assert_eq!(read_word(&mut cpu, 0x300FA), 0x0001);
+        assert_eq!(read_word(&mut cpu, 0x300FC), 0x1000);
+        assert_ne!(read_word(&mut cpu, 0x300FE) & 0x0100, 0);
+        // The trap is still taken, and returns to the INTR handler.
+        assert_eq!(read_word(&mut cpu, 0x300F4), 0x0600);
+        assert_eq!(read_word(&mut cpu, 0x300F6), 0x0000);
+        assert_eq!(read_word(&mut cpu, 0x300F8) & 0x0300, 0);
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0400);
+        assert_eq!(cpu.eu.get_sp(), 0x00F4);
+    }
+
+    #[test]
+    fn test_step_returns_clocks() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        // mov ax, 0x0003; jmp short $+2
+        load(&mut cpu, 0x10000, &[0xB8, 0x03, 0x00, 0xEB, 0x00]);
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert_eq!(cpu.step().unwrap(), 15);
+        assert_eq!(cpu.get_cycles(), 19);
+
+        cpu.request_interrupt(0x08);
+        load(&mut cpu, 0x10005, &[0x90]);
+        assert_eq!(cpu.step().unwrap(), 3 + timing::INTR_CLOCKS);
+    }
+
+    #[test]
+    fn test_into_clocks() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        // into; into
+        load(&mut cpu, 0x10000, &[0xCE, 0xCE]);
+        assert_eq!(cpu.step().unwrap(), 4);
+        cpu.eu.get_flags_mut().set_overflow(true);
+        assert_eq!(cpu.step().unwrap(), 53);
+    }
+
+    #[test]
+    fn test_odd_word_access_costs_a_bus_cycle() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        // mov ax, [0x0010]; mov ax, [0x0011] (ModR/M forms: 8 + EA 6)
+        load(
+            &mut cpu,
+            0x10000,
+            &[0x8B, 0x06, 0x10, 0x00, 0x8B, 0x06, 0x11, 0x00],
+        );
+        assert_eq!(cpu.step().unwrap(), 14);
+        assert_eq!(cpu.step().unwrap(), 14 + timing::EXTRA_BUS_CYCLE_CLOCKS);
+    }
+
+    #[test]
+    fn test_long_prefix_run() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        // 300 x es:, then nop
+        let mut program = vec![0x26; 300];
+        program.push(0x90);
+        load(&mut cpu, 0x10000, &program);
+        assert_eq!(cpu.step().unwrap(), 3 + 300 * timing::PREFIX_CLOCKS);
+        assert_eq!(cpu.biu.get_instruction_pointer(), 301);
+    }
+
+    #[test]
+    fn test_taken_branches_cost_more() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        cpu.eu.set_register16(decode::Register16::CX, 2);
+        // loop $ (to itself)
+        load(&mut cpu, 0x10000, &[0xE2, 0xFE]);
+        assert_eq!(cpu.step().unwrap(), 17);
+        assert_eq!(cpu.step().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_rep_startup_is_charged_once() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        cpu.eu.set_register16(decode::Register16::CX, 3);
+        // rep stosb
+        load(&mut cpu, 0x10000, &[0xF3, 0xAA]);
+        assert_eq!(cpu.step().unwrap(), 19);
+        assert_eq!(cpu.step().unwrap(), 10);
+        assert_eq!(cpu.step().unwrap(), 10);
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0002);
+    }
+
+    #[test]
+    fn test_rep_prefix_clocks_are_charged_once() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        cpu.eu.set_register16(decode::Register16::CX, 3);
+        // rep es: movsb
+        load(&mut cpu, 0x10000, &[0xF3, 0x26, 0xA4]);
+        assert_eq!(
+            cpu.step().unwrap(),
+            17 + timing::REPEAT_STARTUP_CLOCKS + timing::PREFIX_CLOCKS
+        );
+        assert_eq!(cpu.step().unwrap(), 17);
+        assert_eq!(cpu.step().unwrap(), 17);
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0003);
+    }
+
+    #[test]
+    fn test_rep_with_cx_zero_keeps_prefix_clocks() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        // es: rep movsb with CX = 0
+        load(&mut cpu, 0x10000, &[0x26, 0xF3, 0xA4]);
+        assert_eq!(
+            cpu.step().unwrap(),
+            timing::REPEAT_STARTUP_CLOCKS + timing::PREFIX_CLOCKS
+        );
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0003);
+    }
+
+    #[test]
+    fn test_reset_clears_state() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        load(&mut cpu, 0x10000, &[0x90]);
+        cpu.step().unwrap();
+        cpu.raise_nmi();
+        cpu.reset();
+        assert_eq!(cpu.get_cycles(), 0);
+        assert_eq!(cpu.eu.get_sp(), 0);
+        assert!(!cpu.eu.get_flags().get_interrupt_enable());
+        assert!(!cpu.interrupt_pending());
+    }
+
+    #[test]
+    fn test_boot_from_rom() {
+        let mut bus = bus::AddressBus::new();
+        // A 64K BIOS whose reset vector jumps to F000:E05B, where it writes
+        // to its own code (ignored) and halts.
+        let mut rom = vec![0u8; 0x10000];
+        rom[0xFFF0..0xFFF5].copy_from_slice(&[0xEA, 0x5B, 0xE0, 0x00, 0xF0]);
+        // mov ax, 0xF000; mov ds, ax; mov byte [0xE05B], 0x90; hlt
+        rom[0xE05B..0xE066].copy_from_slice(&[
+            0xB8, 0x00, 0xF0, 0x8E, 0xD8, 0xC6, 0x06, 0x5B, 0xE0, 0x90, 0xF4,
+        ]);
+        bus.get_memory_mut().load_rom(0xF0000, &rom).unwrap();
+
+        let mut cpu = Cpu::new(CPUModes::Minimum, &mut bus);
+        cpu.run_until(u64::MAX, |_| false).unwrap();
+        assert!(cpu.eu.is_halted());
+        assert_eq!(cpu.biu.get_code_segment_address(), 0xF000);
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0xE066);
+        assert_eq!(cpu.biu.read_byte(0xFE05B), 0xB8);
+    }
+
+    #[test]
+    fn test_reset_starts_at_ffff0() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        cpu.eu.get_flags_mut().set_trap(true);
+        cpu.reset();
+        assert_eq!(cpu.biu.get_code_segment_address(), 0xFFFF);
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0000);
+        assert_eq!(cpu.biu.get_stack_segment_address(), 0x0000);
+        assert_eq!(cpu.eu.get_flags().to_word(), 0xF002);
+
+        // jmp far 0xF000:0xE05B, as found at the reset vector of a PC BIOS
+        load(&mut cpu, 0xFFFF0, &[0xEA, 0x5B, 0xE0, 0x00, 0xF0]);
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_code_segment_address(), 0xF000);
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0xE05B);
+    }
+
+    #[test]
+    fn test_run_until_cycle_budget() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        // jmp short $ (to itself)
+        load(&mut cpu, 0x10000, &[0xEB, 0xFE]);
+        assert_eq!(cpu.run_until(100, |_| false).unwrap(), 105);
+        assert_eq!(cpu.get_cycles(), 105);
+    }
+
+    #[test]
+    fn test_run_until_predicate() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        // inc ax; jmp short -3
+        load(&mut cpu, 0x10000, &[0x40, 0xEB, 0xFD]);
+        cpu.run_until(u64::MAX, |cpu| {
+            cpu.get_eu().get_register16(decode::Register16::AX) == 5
+        })
+        .unwrap();
+        assert_eq!(cpu.eu.get_register16(decode::Register16::AX), 5);
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0001);
+    }
+
+    #[test]
+    fn test_run_until_stops_when_halted() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        cpu.eu.get_flags_mut().set_interrupt_enable(false);
+        // hlt
+        load(&mut cpu, 0x10000, &[0xF4]);
+        cpu.request_interrupt(0x08);
+        assert_eq!(cpu.run_until(u64::MAX, |_| false).unwrap(), 2);
+        assert!(cpu.eu.is_halted());
+
+        // NMI wakes the CPU, whose handler's IRET returns past the HLT.
+        cpu.raise_nmi();
+        cpu.run_until(u64::MAX, |cpu| {
+            !cpu.get_eu().is_halted() && cpu.get_biu().get_instruction_pointer() == 0x0001
+        })
+        .unwrap();
+        assert_eq!(cpu.biu.get_code_segment_address(), 0x1000);
+    }
+
+    /// Runs a program that overwrites the byte at 1000:`target` with INC AX, then
+    /// executes NOPs up to the short jump at 0x000A, and returns AX.
+    fn run_self_modifying_program(model: biu::Model, target: u8) -> u16 {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        cpu.biu.set_model(model);
+        cpu.biu.set_data_segment_address(0x1000);
+        // mov byte [target], 0x40; nop x4; jmp short $
+        load(
+            &mut cpu,
+            0x10000,
+            &[
+                0xC6, 0x06, target, 0x00, 0x40, 0x90, 0x90, 0x90, 0x90, 0x90, 0xEB, 0xFE,
+            ],
+        );
+        cpu.run_until(u64::MAX, |cpu| {
+            cpu.get_biu().get_instruction_pointer() == 0x000A
+        })
+        .unwrap();
+        cpu.eu.get_register16(decode::Register16::AX)
+    }
+
+    #[test]
+    fn test_self_modifying_code_within_queue_is_not_seen() {
+        // The queue holds 0x0005..=0x0009 when the write happens.
+        assert_eq!(run_self_modifying_program(biu::Model::Intel8086, 0x07), 0);
+        assert_eq!(run_self_modifying_program(biu::Model::Intel8086, 0x09), 0);
+    }
+
+    #[test]
+    fn test_self_modifying_code_beyond_queue_is_seen() {
+        // The 8088 queue only reaches 0x0008, so the write to 0x0009 is fetched.
+        assert_eq!(run_self_modifying_program(biu::Model::Intel8088, 0x07), 0);
+        assert_eq!(run_self_modifying_program(biu::Model::Intel8088, 0x09), 1);
+    }
+
+    #[test]
+    fn test_self_modifying_code_is_seen_after_a_jump() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        cpu.biu.set_data_segment_address(0x1000);
+        // mov byte [0x0007], 0x40; jmp short $+2; inc ax (was nop)
+        load(
+            &mut cpu,
+            0x10000,
+            &[0xC6, 0x06, 0x07, 0x00, 0x40, 0xEB, 0x00, 0x90],
+        );
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.eu.get_register16(decode::Register16::AX), 1);
+    }
+
+    #[test]
+    fn test_trap_steps_through_rep_iterations() {
+        let mut bus = bus::AddressBus::new();
+        let mut cpu = new_cpu(&mut bus);
+        cpu.eu.get_flags_mut().set_interrupt_enable(false);
+        cpu.eu.get_flags_mut().set_trap(true);
+        cpu.eu.set_register16(decode::Register16::CX, 3);
+        // rep stosb
+        load(&mut cpu, 0x10000, &[0xF3, 0xAA]);
+        cpu.step().unwrap();
+        assert_eq!(cpu.biu.get_instruction_pointer(), 0x0400);
+        assert_eq!(read_word(&mut cpu, 0x300FA), 0x0000);
+        assert_eq!(cpu.eu.get_register16(decode::Register16::CX), 2);
+    }
 }
@@ -5,7 +5,7 @@ pub struct Register {
 
 impl Register {
     /// Creates a new `Register` with an initial value of 0x0000.
-    fn new() -> Register {
+    pub fn new() -> Register {
         Register { x: 0x0000 }
     }
 
@@ -29,6 +29,11 @@ impl Register {
         self.x = (self.x & 0x00FF) | ((value as u16) << 8);
     }
 
+    /// Returns the entire 16-bit value of the register.
+    pub fn get(&self) -> u16 {
+        self.x
+    }
+
     /// Sets the entire 16-bit value of the register.
     pub fn set(&mut self, value: u16) {
         self.x = value;
@@ -70,6 +75,12 @@ mod tests {
         assert_eq!(reg.x, 0x7834);
     }
 
+    #[test]
+    fn test_get() {
+        let reg = Register { x: 0x1234 };
+        assert_eq!(reg.get(), 0x1234);
+    }
+
     #[test]
     fn test_set() {
         let mut reg = Register::new();
@@ -0,0 +1,380 @@
+use super::decode::{Instruction, Mnemonic, Operand, OperandSize};
+use super::ea;
+
+/// Clocks taken to enter an INTR (maskable) interrupt handler, including the
+/// two interrupt acknowledge bus cycles.
+pub const INTR_CLOCKS: u32 = 61;
+/// Clocks taken to enter an NMI or single-step interrupt handler.
+pub const NMI_CLOCKS: u32 = 50;
+/// Clocks taken to enter a handler for an interrupt raised by the instruction
+/// itself (divide error).
+pub const INTERNAL_INTERRUPT_CLOCKS: u32 = 51;
+/// Extra clocks INTO takes when OF is set: 53 in all, against 4 when not taken.
+pub const INTO_TAKEN_CLOCKS: u32 = 49;
+/// Clocks taken by the EU for each instruction boundary spent halted.
+pub const HALTED_CLOCKS: u32 = 1;
+
+/// Clocks taken once, before the first iteration of a repeated string instruction.
+pub const REPEAT_STARTUP_CLOCKS: u32 = 9;
+
+/// Extra clocks a conditional jump, LOOP or JCXZ takes when the branch is taken.
+pub const BRANCH_TAKEN_CLOCKS: u32 = 12;
+/// LOOPNE needs two more clocks than the rest of the family when taken.
+pub const LOOPNE_TAKEN_CLOCKS: u32 = 14;
+
+/// Extra clocks per bit for shifts and rotates by CL.
+pub const SHIFT_CLOCKS_PER_BIT: u32 = 4;
+
+/// Clocks taken by the second bus cycle of a word transfer at an odd
+/// address, or of any word transfer on the 8088.
+pub const EXTRA_BUS_CYCLE_CLOCKS: u32 = 4;
+
+/// Clocks taken by each segment override or LOCK prefix byte.
+pub const PREFIX_CLOCKS: u32 = 2;
+
+/// Returns the base execution time of an instruction in clocks, as given in the
+/// 8086 instruction timing tables.
+///
+/// Prefix bytes and the effective-address calculation of a ModR/M memory
+/// operand are included. The data-dependent extras are not: taken branches,
+/// shift counts and the start-up of a repeated string instruction. Multiply
+/// and divide use the fastest time of their documented range.
+pub fn base_clocks(instruction: &Instruction) -> u32 {
+    let destination = instruction.destination;
+    let source = instruction.source;
+    let memory_destination = is_memory(destination);
+    let memory_source = is_memory(source);
+    let immediate_source = is_immediate(source);
+    let byte = instruction.size == OperandSize::Byte;
+    let repeat = instruction.prefixes.repeat.is_some();
+
+    let execution = match instruction.mnemonic {
+        Mnemonic::Mov => match instruction.opcode {
+            // MOV accumulator to or from a direct address
+            0xA0..=0xA3 => 10,
+            _ if memory_destination && immediate_source => 10,
+            _ if memory_destination => 9,
+            _ if memory_source => 8,
+            _ if immediate_source => 4,
+            _ => 2,
+        },
+        Mnemonic::Add
+        | Mnemonic::Adc
+        | Mnemonic::Sub
+        | Mnemonic::Sbb
+        | Mnemonic::And
+        | Mnemonic::Or
+        | Mnemonic::Xor => match () {
+            _ if memory_destination && immediate_source => 17,
+            _ if memory_destination => 16,
+            _ if memory_source => 9,
+            _ if immediate_source => 4,
+            _ => 3,
+        },
+        Mnemonic::Cmp => match () {
+            _ if memory_destination && immediate_source => 10,
+            _ if memory_destination || memory_source => 9,
+            _ if immediate_source => 4,
+            _ => 3,
+        },
+        Mnemonic::Test => match instruction.opcode {
+            // TEST accumulator, immediate
+            0xA8 | 0xA9 => 4,
+            _ if memory_destination && immediate_source => 11,
+            _ if memory_destination || memory_source => 9,
+            _ if immediate_source => 5,
+            _ => 3,
+        },
+        Mnemonic::Inc | Mnemonic::Dec => match instruction.opcode {
+            // One-byte INC/DEC of a word register
+            0x40..=0x4F => 2,
+            _ if memory_destination => 15,
+            _ => 3,
+        },
+        Mnemonic::Neg | Mnemonic::Not => {
+            if memory_destination {
+                16
+            } else {
+                3
+            }
+        }
+        Mnemonic::Shl
+        | Mnemonic::Shr
+        | Mnemonic::Sar
+        | Mnemonic::Rol
+        | Mnemonic::Ror
+        | Mnemonic::Rcl
+        | Mnemonic::Rcr => {
+            let by_cl = !matches!(source, Some(Operand::Immediate8(_)));
+            match (by_cl, memory_destination) {
+                (false, false) => 2,
+                (false, true) => 15,
+                (true, false) => 8,
+                (true, true) => 20,
+            }
+        }
+        Mnemonic::Mul => match (byte, memory_destination) {
+            (true, false) => 70,
+            (true, true) => 76,
+            (false, false) => 118,
+            (false, true) => 124,
+        },
+        Mnemonic::Imul => match (byte, memory_destination) {
+            (true, false) => 80,
+            (true, true) => 86,
+            (false, false) => 128,
+            (false, true) => 134,
+        },
+        Mnemonic::Div => match (byte, memory_destination) {
+            (true, false) => 80,
+            (true, true) => 86,
+            (false, false) => 144,
+            (false, true) => 150,
+        },
+        Mnemonic::Idiv => match (byte, memory_destination) {
+            (true, false) => 101,
+            (true, true) => 107,
+            (false, false) => 165,
+            (false, true) => 171,
+        },
+        Mnemonic::Xchg => match instruction.opcode {
+            // XCHG AX, reg16 (including NOP)
+            0x90..=0x97 => 3,
+            _ if memory_destination || memory_source => 17,
+            _ => 4,
+        },
+        Mnemonic::Lea => 2,
+        Mnemonic::Lds | Mnemonic::Les => 16,
+        Mnemonic::Xlat => 11,
+        Mnemonic::Lahf | Mnemonic::Sahf => 4,
+        Mnemonic::Pushf => 10,
+        Mnemonic::Popf => 8,
+        Mnemonic::Push => match destination {
+            Some(Operand::Memory(_)) => 16,
+            Some(Operand::Segment(_)) => 10,
+            _ => 11,
+        },
+        Mnemonic::Pop => {
+            if memory_destination {
+                17
+            } else {
+                8
+            }
+        }
+        Mnemonic::Cbw => 2,
+        Mnemonic::Cwd => 5,
+        Mnemonic::Aaa | Mnemonic::Aas | Mnemonic::Daa | Mnemonic::Das => 4,
+        Mnemonic::Aam => 83,
+        Mnemonic::Aad => 60,
+        // Repeated forms give the time of one iteration.
+        Mnemonic::Movs if repeat => 17,
+        Mnemonic::Movs => 18,
+        Mnemonic::Cmps => 22,
+        Mnemonic::Scas => 15,
+        Mnemonic::Lods if repeat => 13,
+        Mnemonic::Lods => 12,
+        Mnemonic::Stos if repeat => 10,
+        Mnemonic::Stos => 11,
+        Mnemonic::Jmp => match destination {
+            Some(Operand::Register16(_)) => 11,
+            Some(Operand::Memory(_)) => 18,
+            _ => 15,
+        },
+        Mnemonic::JmpFar => {
+            if memory_destination {
+                24
+            } else {
+                15
+            }
+        }
+        Mnemonic::Call => match destination {
+            Some(Operand::Register16(_)) => 16,
+            Some(Operand::Memory(_)) => 21,
+            _ => 19,
+        },
+        Mnemonic::CallFar => {
+            if memory_destination {
+                37
+            } else {
+                28
+            }
+        }
+        Mnemonic::Ret => {
+            if destination.is_some() {
+                12
+            } else {
+                8
+            }
+        }
+        Mnemonic::RetFar => {
+            if destination.is_some() {
+                17
+            } else {
+                18
+            }
+        }
+        Mnemonic::Jcc(_) => 4,
+        Mnemonic::Loop | Mnemonic::Loopne => 5,
+        Mnemonic::Loope | Mnemonic::Jcxz => 6,
+        Mnemonic::Int => 51,
+        Mnemonic::Int3 => 52,
+        Mnemonic::Into => 4,
+        Mnemonic::Iret => 24,
+        Mnemonic::In | Mnemonic::Out => {
+            if matches!(destination, Some(Operand::Immediate8(_)))
+                || matches!(source, Some(Operand::Immediate8(_)))
+            {
+                10
+            } else {
+                8
+            }
+        }
+        Mnemonic::Esc => {
+            if memory_source {
+                8
+            } else {
+                2
+            }
+        }
+        Mnemonic::Wait => 3,
+        Mnemonic::Clc
+        | Mnemonic::Cmc
+        | Mnemonic::Stc
+        | Mnemonic::Cld
+        | Mnemonic::Std
+        | Mnemonic::Cli
+        | Mnemonic::Sti
+        | Mnemonic::Hlt => 2,
+    };
+    execution + address_clocks(instruction) + prefix_clocks(instruction)
+}
+
+/// Returns the EA calculation time of the instruction's memory operand, if it
+/// has one. MOV between the accumulator and a direct address encodes its
+/// address without a ModR/M byte and needs no calculation.
+fn address_clocks(instruction: &Instruction) -> u32 {
+    if matches!(instruction.opcode, 0xA0..=0xA3) {
+        return 0;
+    }
+    [instruction.destination, instruction.source]
+        .into_iter()
+        .find_map(|operand| match operand {
+            Some(Operand::Memory(memory)) => Some(ea::clocks(&memory)),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+/// Returns the clocks taken by the instruction's prefix bytes. The time of a
+/// repeat prefix is part of the repeated string instruction's own timing.
+pub fn prefix_clocks(instruction: &Instruction) -> u32 {
+    let prefixes = &instruction.prefixes;
+    let charged = prefixes.count as u32 - u32::from(prefixes.repeat.is_some());
+    PREFIX_CLOCKS * charged
+}
+
+fn is_memory(operand: Option<Operand>) -> bool {
+    matches!(operand, Some(Operand::Memory(_)))
+}
+
+fn is_immediate(operand: Option<Operand>) -> bool {
+    matches!(
+        operand,
+        Some(Operand::Immediate8(_) | Operand::Immediate16(_) | Operand::SignExtended8(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::decode::decode;
+
+    fn clocks(bytes: &[u8]) -> u32 {
+        base_clocks(&decode(&mut bytes.iter()).unwrap())
+    }
+
+    #[test]
+    fn test_mov_forms() {
+        // mov ax, bx
+        assert_eq!(clocks(&[0x89, 0xD8]), 2);
+        // mov ax, 0x1234
+        assert_eq!(clocks(&[0xB8, 0x34, 0x12]), 4);
+        // mov [bx], ax: 9 + EA
+        assert_eq!(clocks(&[0x89, 0x07]), 9 + 5);
+        // mov ax, [bx+si+4]: 8 + EA
+        assert_eq!(clocks(&[0x8B, 0x40, 0x04]), 8 + 11);
+        // mov byte [bx], 0x12: 10 + EA
+        assert_eq!(clocks(&[0xC6, 0x07, 0x12]), 10 + 5);
+        // mov al, [0x1234] has no EA calculation
+        assert_eq!(clocks(&[0xA0, 0x34, 0x12]), 10);
+        // mov ax, [0x1234] through ModR/M
+        assert_eq!(clocks(&[0x8B, 0x06, 0x34, 0x12]), 8 + 6);
+    }
+
+    #[test]
+    fn test_alu_forms() {
+        // add ax, bx
+        assert_eq!(clocks(&[0x01, 0xD8]), 3);
+        // add [bx], ax
+        assert_eq!(clocks(&[0x01, 0x07]), 16 + 5);
+        // add word [bx], 1
+        assert_eq!(clocks(&[0x83, 0x07, 0x01]), 17 + 5);
+        // cmp [bp+di], ax
+        assert_eq!(clocks(&[0x39, 0x03]), 9 + 7);
+        // test al, 1
+        assert_eq!(clocks(&[0xA8, 0x01]), 4);
+        // inc ax; inc al
+        assert_eq!(clocks(&[0x40]), 2);
+        assert_eq!(clocks(&[0xFE, 0xC0]), 3);
+    }
+
+    #[test]
+    fn test_shift_forms() {
+        // shl ax, 1
+        assert_eq!(clocks(&[0xD1, 0xE0]), 2);
+        // shl ax, cl
+        assert_eq!(clocks(&[0xD3, 0xE0]), 8);
+        // shl word [bx], cl
+        assert_eq!(clocks(&[0xD3, 0x27]), 20 + 5);
+    }
+
+    #[test]
+    fn test_control_transfer_forms() {
+        // jmp short; jmp ax; jmp [bx]; jmp far
+        assert_eq!(clocks(&[0xEB, 0x00]), 15);
+        assert_eq!(clocks(&[0xFF, 0xE0]), 11);
+        assert_eq!(clocks(&[0xFF, 0x27]), 18 + 5);
+        assert_eq!(clocks(&[0xEA, 0x00, 0x00, 0x00, 0x00]), 15);
+        // call near; call far
+        assert_eq!(clocks(&[0xE8, 0x00, 0x00]), 19);
+        assert_eq!(clocks(&[0x9A, 0x00, 0x00, 0x00, 0x00]), 28);
+        // ret; ret 2; retf
+        assert_eq!(clocks(&[0xC3]), 8);
+        assert_eq!(clocks(&[0xC2, 0x02, 0x00]), 12);
+        assert_eq!(clocks(&[0xCB]), 18);
+        // jz (not taken); loop; loopne
+        assert_eq!(clocks(&[0x74, 0x00]), 4);
+        assert_eq!(clocks(&[0xE2, 0x00]), 5);
+        assert_eq!(clocks(&[0xE0, 0x00]), 5);
+    }
+
+    #[test]
+    fn test_prefixes_add_clocks() {
+        // es: mov ax, [0x0010]
+        assert_eq!(clocks(&[0x26, 0xA1, 0x10, 0x00]), 10 + 2);
+        // lock add [bx], ax
+        assert_eq!(clocks(&[0xF0, 0x01, 0x07]), 16 + 5 + 2);
+        // rep cs: movsb
+        assert_eq!(clocks(&[0xF3, 0x2E, 0xA4]), 19);
+    }
+
+    #[test]
+    fn test_string_forms() {
+        // movsb; rep movsb
+        assert_eq!(clocks(&[0xA4]), 18);
+        assert_eq!(clocks(&[0xF3, 0xA4]), 17);
+        // stosb; rep stosb
+        assert_eq!(clocks(&[0xAA]), 11);
+        assert_eq!(clocks(&[0xF3, 0xAA]), 10);
+    }
+}
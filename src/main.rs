@@ -1,4 +1,130 @@
-pub mod cpu;
-fn main() {
-    println!("Hello, world!");
+use std::env;
+use std::fs;
+use std::process::ExitCode;
+
+use intel_8086::cpu::disasm::{self, Syntax};
+
+const USAGE: &str = "usage: intel_8086 disasm [--intel | --nasm] [--origin SEGMENT:OFFSET] FILE";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("disasm") => disasm_command(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The options of the `disasm` subcommand.
+#[derive(Debug, PartialEq, Eq)]
+struct DisasmOptions<'a> {
+    syntax: Syntax,
+    origin: (u16, u16),
+    path: &'a str,
+}
+
+/// Disassembles a raw binary file, printing one instruction per line. With
+/// `--nasm` the lines are laid out exactly as ndisasm prints them, so the
+/// output can be diffed against `ndisasm -o OFFSET`.
+fn disasm_command(args: &[String]) -> Result<(), String> {
+    let options = parse_disasm_args(args)?;
+    let path = options.path;
+    let bytes = fs::read(path).map_err(|error| format!("{path}: {error}"))?;
+    let (cs, ip) = options.origin;
+    for line in disasm::disassemble(&bytes, cs, ip, options.syntax) {
+        match options.syntax {
+            Syntax::Intel => println!("{line}"),
+            Syntax::Nasm => println!("{}", line.to_ndisasm()),
+        }
+    }
+    Ok(())
+}
+
+fn parse_disasm_args(args: &[String]) -> Result<DisasmOptions<'_>, String> {
+    let mut syntax = Syntax::default();
+    let mut origin = (0, 0);
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--intel" => syntax = Syntax::Intel,
+            "--nasm" => syntax = Syntax::Nasm,
+            "--origin" => {
+                let value = args.next().ok_or(USAGE)?;
+                origin = parse_origin(value).ok_or(format!("invalid origin: {value}"))?;
+            }
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg.as_str()),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    Ok(DisasmOptions {
+        syntax,
+        origin,
+        path: path.ok_or(USAGE)?,
+    })
+}
+
+/// Parses a `SEGMENT:OFFSET` pair of hex numbers.
+fn parse_origin(value: &str) -> Option<(u16, u16)> {
+    let (segment, offset) = value.split_once(':')?;
+    Some((
+        u16::from_str_radix(segment, 16).ok()?,
+        u16::from_str_radix(offset, 16).ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_origin() {
+        assert_eq!(parse_origin("1000:0100"), Some((0x1000, 0x0100)));
+        assert_eq!(parse_origin("f000:FFF0"), Some((0xF000, 0xFFF0)));
+        assert_eq!(parse_origin("0100"), None);
+        assert_eq!(parse_origin("10000:0"), None);
+        assert_eq!(parse_origin("0:xyz"), None);
+    }
+
+    #[test]
+    fn test_parse_disasm_args() {
+        assert_eq!(
+            parse_disasm_args(&args(&["boot.bin"])),
+            Ok(DisasmOptions {
+                syntax: Syntax::Intel,
+                origin: (0, 0),
+                path: "boot.bin"
+            })
+        );
+        assert_eq!(
+            parse_disasm_args(&args(&["--nasm", "--origin", "0:7C00", "boot.bin"])),
+            Ok(DisasmOptions {
+                syntax: Syntax::Nasm,
+                origin: (0, 0x7C00),
+                path: "boot.bin"
+            })
+        );
+        assert_eq!(
+            parse_disasm_args(&args(&["--origin", "7C00", "boot.bin"])),
+            Err("invalid origin: 7C00".to_string())
+        );
+        for bad in [
+            &[][..],
+            &["--origin"],
+            &["--att", "boot.bin"],
+            &["a.bin", "b.bin"],
+        ] {
+            assert_eq!(parse_disasm_args(&args(bad)), Err(USAGE.to_string()));
+        }
+    }
 }